@@ -0,0 +1,96 @@
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+// Axis-aligned bounding box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    // Box with `a` and `b` as opposite corners, in any order
+    pub fn new(a: Vec3, b: Vec3) -> Self {
+        Aabb {
+            min: a.min(&b),
+            max: a.max(&b),
+        }
+    }
+
+    // Smallest box containing both boxes
+    pub fn surrounding(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(&other.min),
+            max: self.max.max(&other.max),
+        }
+    }
+
+    // Widen the dimensions thinner than `delta` to `delta`, so that flat
+    // primitives get a box with a volume
+    pub fn pad(&self, delta: f32) -> Aabb {
+        let widen = |min: f32, max: f32| {
+            if max - min < delta {
+                let mid = 0.5 * (min + max);
+                (mid - 0.5 * delta, mid + 0.5 * delta)
+            } else {
+                (min, max)
+            }
+        };
+        let (x0, x1) = widen(self.min.x, self.max.x);
+        let (y0, y1) = widen(self.min.y, self.max.y);
+        let (z0, z1) = widen(self.min.z, self.max.z);
+        Aabb {
+            min: Vec3::new(x0, y0, z0),
+            max: Vec3::new(x1, y1, z1),
+        }
+    }
+
+    // Whether the ray crosses the box between `t_min` and `t_max` (slab test)
+    pub fn hit(&self, r: &Ray, mut t_min: f32, mut t_max: f32) -> bool {
+        for axis in 0..3 {
+            let inv_d = 1.0 / r.direction[axis];
+            let mut t0 = (self.min[axis] - r.origin[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - r.origin[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max <= t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hit_box() {
+        let b = Aabb::new(Vec3::new(1.0, 1.0, 1.0), Vec3::new(-1.0, -1.0, -1.0));
+        assert_eq!(b.min, Vec3::new(-1.0, -1.0, -1.0));
+        let towards = Ray::new(Vec3::new(0.5, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(b.hit(&towards, 0.0, f32::MAX));
+        assert!(!b.hit(&towards, 0.0, 3.0));
+        let away = Ray::new(Vec3::new(0.5, 0.5, 5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(!b.hit(&away, 0.0, f32::MAX));
+        let beside = Ray::new(Vec3::new(2.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(!b.hit(&beside, 0.0, f32::MAX));
+    }
+
+    #[test]
+    fn surrounding_and_pad() {
+        let a = Aabb::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 0.0));
+        let b = Aabb::new(Vec3::new(2.0, -1.0, 0.0), Vec3::new(3.0, 0.0, 0.0));
+        let s = a.surrounding(&b);
+        assert_eq!(s.min, Vec3::new(0.0, -1.0, 0.0));
+        assert_eq!(s.max, Vec3::new(3.0, 1.0, 0.0));
+        let p = s.pad(0.5);
+        assert_eq!(p.min.z, -0.25);
+        assert_eq!(p.max.z, 0.25);
+        assert_eq!(p.max.x, 3.0);
+    }
+}
@@ -0,0 +1,226 @@
+use crate::aabb::Aabb;
+use crate::error::{Error, Result};
+use crate::transform::{Decomposition, Transform};
+use crate::vec3::Vec3;
+
+// Transform changing over time, given at keyframes. Between two keyframes
+// the transforms are decomposed and interpolated: translation and scale
+// linearly, rotation with quaternion slerp, so that spinning objects turn
+// at constant speed instead of shrinking through the linear blend of two
+// rotation matrices. Before the first and after the last keyframe the
+// transform stays fixed.
+#[derive(Debug, Clone)]
+pub struct AnimatedTransform {
+    keys: Vec<Key>,
+}
+
+#[derive(Debug, Clone)]
+struct Key {
+    time: f32,
+    transform: Transform,
+    parts: Decomposition,
+}
+
+impl AnimatedTransform {
+    // The same transform at all times
+    pub fn fixed(transform: Transform) -> Self {
+        AnimatedTransform {
+            keys: vec![Key {
+                time: 0.0,
+                transform,
+                parts: transform.decompose(),
+            }],
+        }
+    }
+
+    // `keyframes` are (time, transform) pairs in increasing order of time
+    pub fn new(keyframes: Vec<(f32, Transform)>) -> Result<Self> {
+        if keyframes.is_empty() {
+            return Err(Error::InvalidScene(
+                "an animated transform needs at least one keyframe".to_string(),
+            ));
+        }
+        if keyframes.iter().any(|(time, _)| !time.is_finite())
+            || keyframes.windows(2).any(|w| w[0].0 >= w[1].0)
+        {
+            return Err(Error::InvalidScene(
+                "keyframe times must be finite and increasing".to_string(),
+            ));
+        }
+        Ok(AnimatedTransform {
+            keys: keyframes
+                .into_iter()
+                .map(|(time, transform)| Key {
+                    time,
+                    transform,
+                    parts: transform.decompose(),
+                })
+                .collect(),
+        })
+    }
+
+    pub fn is_animated(&self) -> bool {
+        self.keys.len() > 1
+    }
+
+    pub fn at(&self, time: f32) -> Transform {
+        // index of the first keyframe after `time`
+        let next = self.keys.partition_point(|k| k.time <= time);
+        if next == 0 {
+            return self.keys[0].transform;
+        }
+        if next == self.keys.len() {
+            return self.keys[next - 1].transform;
+        }
+        let (a, b) = (&self.keys[next - 1], &self.keys[next]);
+        let t = (time - a.time) / (b.time - a.time);
+        a.parts
+            .interpolate(&b.parts, t)
+            .to_transform()
+            .unwrap_or(if t < 0.5 { a.transform } else { b.transform })
+    }
+
+    // Box containing `b` moved by the transforms between `time0` and `time1`.
+    // The motion is sampled every half degree of rotation at most, and the box
+    // widened by how far a corner of `b` strays between two samples.
+    pub fn bounding_box(&self, b: &Aabb, time0: f32, time1: f32) -> Aabb {
+        let mut bbox = self.at(time0).bounding_box(b);
+        if !self.is_animated() || time1 <= time0 {
+            return bbox;
+        }
+
+        let corner = |i: usize| {
+            Vec3::new(
+                if i & 1 == 0 { b.min.x } else { b.max.x },
+                if i & 2 == 0 { b.min.y } else { b.max.y },
+                if i & 4 == 0 { b.min.z } else { b.max.z },
+            )
+        };
+        let mut times = vec![time0, time1];
+        let mut margin = 0.0f32;
+        for pair in self.keys.windows(2) {
+            let (t0, t1) = (pair[0].time.max(time0), pair[1].time.min(time1));
+            if t0 >= t1 {
+                continue;
+            }
+            let angle = pair[0].parts.rotation.angle_to(&pair[1].parts.rotation);
+            let steps = (angle.to_degrees() / 2.0).ceil().max(1.0) as usize * 4;
+            times.extend((0..=steps).map(|i| t0 + (t1 - t0) * i as f32 / steps as f32));
+
+            // The scale is blended linearly, so the corners are never further
+            // from the rotation center than at one of the two keyframes
+            let radius = (0..8)
+                .flat_map(|i| pair.iter().map(move |k| (i, k)))
+                .map(|(i, k)| k.parts.scale.transform_vector(&corner(i)).length())
+                .fold(0.0, f32::max);
+            // Between two samples a point strays from the chord joining them
+            // by at most 1 - cos(half the angle) of its distance to the center
+            let half = 0.5 * angle / steps as f32;
+            margin = margin.max((1.0 - half.cos()) * radius);
+        }
+        for time in times {
+            bbox = bbox.surrounding(&self.at(time).bounding_box(b));
+        }
+
+        let pad = Vec3::new(margin, margin, margin);
+        Aabb::new(bbox.min - pad, bbox.max + pad)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn interpolate_keyframes() {
+        let axis = Vec3::new(0.0, 1.0, 0.0);
+        let motion = AnimatedTransform::new(vec![
+            (0.0, Transform::identity()),
+            (
+                1.0,
+                Transform::rotate(axis, 90.0).then(&Transform::translate(Vec3::new(4.0, 0.0, 0.0))),
+            ),
+            (
+                2.0,
+                Transform::scale(Vec3::new(2.0, 2.0, 2.0))
+                    .then(&Transform::rotate(axis, 180.0))
+                    .then(&Transform::translate(Vec3::new(4.0, 0.0, 0.0))),
+            ),
+        ])
+        .unwrap();
+        let p = Vec3::new(1.0, 0.0, 0.0);
+
+        assert_close(motion.at(-1.0).point(&p), p);
+        assert_close(motion.at(0.0).point(&p), p);
+        // halfway: a rotation of 45 degrees, not the shorter blend of matrices
+        let expected = Transform::rotate(axis, 45.0)
+            .then(&Transform::translate(Vec3::new(2.0, 0.0, 0.0)))
+            .point(&p);
+        assert_close(motion.at(0.5).point(&p), expected);
+        assert!((motion.at(0.5).vector(&p).length() - 1.0).abs() < 1e-4);
+        assert_close(
+            motion.at(1.5).point(&p),
+            Vec3::new(4.0, 0.0, 0.0) + Transform::rotate(axis, 135.0).point(&(1.5 * p)),
+        );
+        assert_close(motion.at(3.0).point(&p), Vec3::new(2.0, 0.0, 0.0));
+
+        assert!(AnimatedTransform::new(vec![]).is_err());
+        assert!(AnimatedTransform::new(vec![
+            (1.0, Transform::identity()),
+            (0.0, Transform::identity())
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn bounding_box_covers_the_motion() {
+        // a unit cube spinning a half turn around the y axis through x = 2
+        let axis = Vec3::new(0.0, 1.0, 0.0);
+        let around = |degrees: f32| {
+            Transform::translate(Vec3::new(-2.0, 0.0, 0.0))
+                .then(&Transform::rotate(axis, degrees))
+                .then(&Transform::translate(Vec3::new(2.0, 0.0, 0.0)))
+        };
+        let motion =
+            AnimatedTransform::new(vec![(0.0, around(0.0)), (1.0, around(180.0))]).unwrap();
+        let cube = Aabb::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0));
+        let bbox = motion.bounding_box(&cube, 0.0, 1.0);
+
+        for i in 0..=1000 {
+            let t = motion.at(i as f32 / 1000.0).bounding_box(&cube);
+            assert!(t.min.x >= bbox.min.x && t.min.z >= bbox.min.z);
+            assert!(t.max.x <= bbox.max.x && t.max.z <= bbox.max.z);
+        }
+        // and not much more: the swept disk has a radius of sqrt(5)
+        assert!(bbox.max.z < 5.0f32.sqrt() + 0.01);
+        assert!(bbox.min.x > 2.0 - 5.0f32.sqrt() - 0.01);
+    }
+
+    #[test]
+    fn bounding_box_covers_a_small_turn_far_from_the_pivot() {
+        // a small box 100 units from the y axis, turning 2 degrees so that it
+        // is furthest along x halfway between two samples
+        let axis = Vec3::new(0.0, 1.0, 0.0);
+        let motion = AnimatedTransform::new(vec![
+            (0.0, Transform::rotate(axis, -0.25)),
+            (1.0, Transform::rotate(axis, 1.75)),
+        ])
+        .unwrap();
+        let small = Aabb::new(
+            Vec3::new(99.999, -0.001, -0.001),
+            Vec3::new(100.001, 0.001, 0.001),
+        );
+        let bbox = motion.bounding_box(&small, 0.0, 1.0);
+
+        for i in 0..=1000 {
+            let t = motion.at(i as f32 / 1000.0).bounding_box(&small);
+            assert!(t.min.x >= bbox.min.x && t.min.z >= bbox.min.z);
+            assert!(t.max.x <= bbox.max.x && t.max.z <= bbox.max.z);
+        }
+        assert!(bbox.max.x < 100.001 + 0.01);
+    }
+}
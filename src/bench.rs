@@ -0,0 +1,318 @@
+// Benchmark suite of the tracer: intersection, traversal, material
+// scattering and full-frame renders at fixed seeds. Results can be saved
+// as JSON to track performance across commits.
+use crate::bvh::Bvh;
+use crate::error::{Error, Result};
+use crate::hitable::{HitRecord, Hitable};
+use crate::material::{Dielectric, Lambertian, Material, Metal};
+use crate::random;
+use crate::ray::Ray;
+use crate::renderer::{RenderSettings, Renderer};
+use crate::scene;
+use crate::sphere::Sphere;
+use crate::vec3::Vec3;
+
+use std::hint::black_box;
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Number of rays or scatter events in one iteration of a micro benchmark
+const BATCH: usize = 1024;
+
+const SEED: u64 = 2019;
+
+#[derive(Debug, Clone)]
+pub struct BenchResult {
+    pub name: String,
+    // What is counted in `items_per_iter`, e.g. "rays"
+    pub unit: &'static str,
+    pub items_per_iter: u64,
+    pub iterations: u64,
+    pub time: Duration,
+}
+
+impl BenchResult {
+    pub fn time_per_iter(&self) -> Duration {
+        self.time / self.iterations.max(1) as u32
+    }
+
+    // Throughput, e.g. rays per second
+    pub fn items_per_second(&self) -> f64 {
+        let secs = self.time.as_secs_f64();
+        if secs > 0.0 {
+            (self.items_per_iter * self.iterations) as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BenchOptions {
+    // Each benchmark is repeated until it has run for this long
+    pub min_time: Duration,
+    // Settings of the full-frame renders
+    pub render: RenderSettings,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        BenchOptions {
+            min_time: Duration::from_secs(2),
+            render: RenderSettings {
+                width: 200,
+                height: 150,
+                samples: 8,
+                seed: Some(SEED),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl BenchOptions {
+    // Short runs, to check that the suite works
+    pub fn quick() -> Self {
+        BenchOptions {
+            min_time: Duration::from_millis(100),
+            render: RenderSettings {
+                width: 40,
+                height: 30,
+                samples: 2,
+                seed: Some(SEED),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+// Run `f` repeatedly for at least `min_time`, after one warm-up run
+fn measure<F: FnMut()>(
+    name: &str,
+    unit: &'static str,
+    items_per_iter: u64,
+    min_time: Duration,
+    mut f: F,
+) -> BenchResult {
+    f();
+    let start = Instant::now();
+    let mut iterations: u64 = 0;
+    while iterations == 0 || start.elapsed() < min_time {
+        f();
+        iterations += 1;
+    }
+    BenchResult {
+        name: name.to_string(),
+        unit,
+        items_per_iter,
+        iterations,
+        time: start.elapsed(),
+    }
+}
+
+// Rays from random points around the origin towards random points of a unit sphere at the origin
+fn random_rays() -> Vec<Ray> {
+    random::seed(SEED);
+    let point = |scale: f32| {
+        scale
+            * Vec3::new(
+                2.0 * random::next_f32() - 1.0,
+                2.0 * random::next_f32() - 1.0,
+                2.0 * random::next_f32() - 1.0,
+            )
+    };
+    (0..BATCH)
+        .map(|_| {
+            let origin = point(10.0);
+            let target = point(1.0);
+            Ray::new(origin, target - origin)
+        })
+        .collect()
+}
+
+// Primary rays of the random scene camera
+fn camera_rays(aspect: f32) -> Vec<Ray> {
+    let cam = scene::random_scene_camera(aspect);
+    random::seed(SEED);
+    (0..BATCH)
+        .map(|_| cam.get_ray(random::next_f32(), random::next_f32()))
+        .collect()
+}
+
+fn bench_hits(name: &str, world: &dyn Hitable, rays: &[Ray], min_time: Duration) -> BenchResult {
+    measure(name, "rays", rays.len() as u64, min_time, || {
+        for r in rays {
+            black_box(world.hit(black_box(r), 0.001, f32::MAX));
+        }
+    })
+}
+
+fn bench_scatter(name: &str, material: &dyn Material, min_time: Duration) -> BenchResult {
+    let rec = HitRecord {
+        p: Vec3::new(0.0, 0.0, 1.0),
+        ..HitRecord::facing_z(material)
+    };
+    let r_in = Ray::new(Vec3::new(-1.0, 0.0, 2.0), Vec3::new(1.0, 0.0, -1.0));
+    random::seed(SEED);
+    measure(name, "scatters", BATCH as u64, min_time, || {
+        for _ in 0..BATCH {
+            black_box(material.scatter(black_box(&r_in), &rec));
+        }
+    })
+}
+
+fn bench_render(
+    name: &str,
+    world: &dyn Hitable,
+    settings: RenderSettings,
+    threads: Option<usize>,
+    min_time: Duration,
+) -> Result<BenchResult> {
+    let cam = scene::random_scene_camera(settings.aspect());
+    let renderer = Renderer::new(settings);
+    // the settings are checked by the first render, so the timed ones cannot
+    // fail
+    let rays = renderer.render(world, &cam)?.stats.rays.total_rays();
+
+    let mut builder = rayon::ThreadPoolBuilder::new();
+    if let Some(n) = threads {
+        builder = builder.num_threads(n);
+    }
+    let pool = builder.build().map_err(|e| Error::Io {
+        path: None,
+        source: io::Error::other(e),
+    })?;
+    Ok(pool.install(|| {
+        measure(name, "rays", rays, min_time, || {
+            black_box(renderer.render(world, &cam).ok());
+        })
+    }))
+}
+
+// Run the whole suite. `on_result` is called as soon as each benchmark is done.
+pub fn run<F: FnMut(&BenchResult)>(
+    options: &BenchOptions,
+    mut on_result: F,
+) -> Result<Vec<BenchResult>> {
+    let min_time = options.min_time;
+    let mut results: Vec<BenchResult> = vec![];
+    let mut push = |result: BenchResult| {
+        on_result(&result);
+        results.push(result);
+    };
+
+    let sphere = Sphere::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        1.0,
+        Arc::new(Lambertian::new(0.5, 0.5, 0.5)),
+    );
+    push(bench_hits("sphere/hit", &sphere, &random_rays(), min_time));
+
+    let world = scene::random_scene_with_seed(SEED);
+    let rays = camera_rays(options.render.aspect());
+    push(bench_hits(
+        "hitable_list/random_scene",
+        &world,
+        &rays,
+        min_time,
+    ));
+    let bvh = Bvh::new(world.hitables.clone(), 0.0, 0.0);
+    push(bench_hits("bvh/random_scene", &bvh, &rays, min_time));
+
+    push(bench_scatter(
+        "scatter/lambertian",
+        &Lambertian::new(0.5, 0.5, 0.5),
+        min_time,
+    ));
+    push(bench_scatter(
+        "scatter/metal",
+        &Metal::new((0.7, 0.6, 0.5), 0.3),
+        min_time,
+    ));
+    push(bench_scatter(
+        "scatter/dielectric",
+        &Dielectric::new(1.5),
+        min_time,
+    ));
+
+    push(bench_render(
+        "render/random_scene/1-thread",
+        &world,
+        options.render,
+        Some(1),
+        min_time,
+    )?);
+    push(bench_render(
+        "render/random_scene/parallel",
+        &world,
+        options.render,
+        None,
+        min_time,
+    )?);
+
+    Ok(results)
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// Serialize results to JSON, tagged with the commit they were measured on
+pub fn to_json(results: &[BenchResult], commit: Option<&str>) -> String {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let entries: Vec<String> = results
+        .iter()
+        .map(|r| {
+            format!(
+                "    {{\"name\": {}, \"unit\": {}, \"iterations\": {}, \"ns_per_iter\": {}, \"per_second\": {:.1}}}",
+                json_string(&r.name),
+                json_string(r.unit),
+                r.iterations,
+                r.time_per_iter().as_nanos(),
+                r.items_per_second()
+            )
+        })
+        .collect();
+    format!(
+        "{{\n  \"commit\": {},\n  \"timestamp\": {},\n  \"results\": [\n{}\n  ]\n}}\n",
+        commit.map_or("null".to_string(), json_string),
+        timestamp,
+        entries.join(",\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_output() {
+        let results = vec![BenchResult {
+            name: "sphere/hit".to_string(),
+            unit: "rays",
+            items_per_iter: 1000,
+            iterations: 4,
+            time: Duration::from_millis(2),
+        }];
+        let json = to_json(&results, Some("abc\"123"));
+        assert!(json.contains("\"commit\": \"abc\\\"123\""));
+        assert!(json.contains(
+            "{\"name\": \"sphere/hit\", \"unit\": \"rays\", \"iterations\": 4, \"ns_per_iter\": 500000, \"per_second\": 2000000.0}"
+        ));
+        assert!(to_json(&[], None).contains("\"commit\": null"));
+    }
+}
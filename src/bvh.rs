@@ -0,0 +1,249 @@
+use crate::aabb::Aabb;
+use crate::hitable::{HitRecord, Hitable};
+use crate::ray::Ray;
+use crate::stats;
+
+use std::sync::Arc;
+
+// Most objects in a leaf
+const MAX_LEAF_SIZE: usize = 2;
+
+// Bounding volume hierarchy over a set of objects. Nodes are split at the
+// median of the object centers along the longest axis.
+//
+// The boxes enclose the objects over the shutter interval given to `new`,
+// which must contain the times of the rays when objects move.
+//
+// A `Bvh` is itself a `Hitable`, so it can be shared by several `Instance`s
+// which are in turn put in a `Bvh`: a two-level hierarchy where the bottom
+// level is built once per geometry and the top level once per scene.
+pub struct Bvh {
+    nodes: Vec<Node>,
+    objects: Vec<Arc<dyn Hitable>>,
+    // objects without a bounding box, tested by every ray
+    unbounded: Vec<Arc<dyn Hitable>>,
+}
+
+struct Node {
+    bbox: Aabb,
+    kind: NodeKind,
+}
+
+enum NodeKind {
+    // objects[start..start + count]
+    Leaf { start: usize, count: usize },
+    // the left child is the next node
+    Interior { right: usize, axis: usize },
+}
+
+impl Bvh {
+    pub fn new(objects: Vec<Arc<dyn Hitable>>, time0: f32, time1: f32) -> Self {
+        let mut bounded: Vec<(Aabb, Arc<dyn Hitable>)> = vec![];
+        let mut unbounded = vec![];
+        for object in objects {
+            match object.bounding_box(time0, time1) {
+                Some(bbox) => bounded.push((bbox, object)),
+                None => unbounded.push(object),
+            }
+        }
+
+        let mut bvh = Bvh {
+            nodes: vec![],
+            objects: Vec::with_capacity(bounded.len()),
+            unbounded,
+        };
+        if !bounded.is_empty() {
+            bvh.build(&mut bounded);
+        }
+        bvh.objects = bounded.into_iter().map(|(_, object)| object).collect();
+        bvh
+    }
+
+    // Number of objects, bounded or not
+    pub fn len(&self) -> usize {
+        self.objects.len() + self.unbounded.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Add the nodes of `objects`, which start at `offset` in the final
+    // object list, and reorder them. Returns the index of the subtree root.
+    fn build_range(&mut self, objects: &mut [(Aabb, Arc<dyn Hitable>)], offset: usize) -> usize {
+        let bbox = objects[1..]
+            .iter()
+            .fold(objects[0].0, |acc, (b, _)| acc.surrounding(b));
+        let index = self.nodes.len();
+        if objects.len() <= MAX_LEAF_SIZE {
+            self.nodes.push(Node {
+                bbox,
+                kind: NodeKind::Leaf {
+                    start: offset,
+                    count: objects.len(),
+                },
+            });
+            return index;
+        }
+
+        let center = |b: &Aabb| 0.5 * (b.min + b.max);
+        let first = center(&objects[0].0);
+        let centers = objects[1..]
+            .iter()
+            .fold(Aabb::new(first, first), |acc, (b, _)| {
+                let c = center(b);
+                acc.surrounding(&Aabb::new(c, c))
+            });
+        let extent = centers.max - centers.min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+
+        let mid = objects.len() / 2;
+        objects.select_nth_unstable_by(mid, |(a, _), (b, _)| {
+            center(a)[axis].total_cmp(&center(b)[axis])
+        });
+
+        self.nodes.push(Node {
+            bbox,
+            kind: NodeKind::Interior { right: 0, axis },
+        });
+        let (left, right) = objects.split_at_mut(mid);
+        self.build_range(left, offset);
+        let right_index = self.build_range(right, offset + mid);
+        self.nodes[index].kind = NodeKind::Interior {
+            right: right_index,
+            axis,
+        };
+        index
+    }
+
+    fn build(&mut self, objects: &mut [(Aabb, Arc<dyn Hitable>)]) {
+        self.nodes.reserve(2 * objects.len());
+        self.build_range(objects, 0);
+    }
+}
+
+impl Hitable for Bvh {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let mut closest_so_far = t_max;
+        let mut hit_anything: Option<HitRecord> = None;
+
+        stats::add_intersection_tests(self.unbounded.len() as u64);
+        for object in &self.unbounded {
+            if let Some(rec) = object.hit(r, t_min, closest_so_far) {
+                closest_so_far = rec.t;
+                hit_anything = Some(rec);
+            }
+        }
+        if self.nodes.is_empty() {
+            return hit_anything;
+        }
+
+        let mut stack: Vec<usize> = Vec::with_capacity(64);
+        stack.push(0);
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !node.bbox.hit(r, t_min, closest_so_far) {
+                continue;
+            }
+            match node.kind {
+                NodeKind::Leaf { start, count } => {
+                    stats::add_intersection_tests(count as u64);
+                    for object in &self.objects[start..start + count] {
+                        if let Some(rec) = object.hit(r, t_min, closest_so_far) {
+                            closest_so_far = rec.t;
+                            hit_anything = Some(rec);
+                        }
+                    }
+                }
+                NodeKind::Interior { right, axis } => {
+                    // visit the nearer child first
+                    if r.direction[axis] < 0.0 {
+                        stack.push(index + 1);
+                        stack.push(right);
+                    } else {
+                        stack.push(right);
+                        stack.push(index + 1);
+                    }
+                }
+            }
+        }
+        hit_anything
+    }
+
+    // The box over the interval the hierarchy was built for
+    fn bounding_box(&self, _time0: f32, _time1: f32) -> Option<Aabb> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        self.nodes.first().map(|root| root.bbox)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hitable_list::HitableList;
+    use crate::material::Lambertian;
+    use crate::quad::Quad;
+    use crate::random;
+    use crate::scene;
+    use crate::sphere::Sphere;
+    use crate::vec3::Vec3;
+
+    fn random_vec() -> Vec3 {
+        Vec3::new(random::next_f32(), random::next_f32(), random::next_f32()) * 2.0
+            - Vec3::new(1.0, 1.0, 1.0)
+    }
+
+    #[test]
+    fn same_hits_as_a_list() {
+        random::seed(5);
+        let list = scene::random_scene_with_seed(5);
+        let bvh = Bvh::new(list.hitables.clone(), 0.0, 0.0);
+        assert_eq!(bvh.len(), list.hitables.len());
+
+        for _ in 0..2000 {
+            let r = Ray::new(Vec3::new(0.0, 1.0, 0.0) + random_vec() * 10.0, random_vec());
+            let expected = list.hit(&r, 0.001, f32::MAX).map(|rec| rec.t);
+            assert_eq!(bvh.hit(&r, 0.001, f32::MAX).map(|rec| rec.t), expected);
+        }
+    }
+
+    #[test]
+    fn unbounded_and_empty() {
+        assert!(Bvh::new(vec![], 0.0, 0.0)
+            .hit(
+                &Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)),
+                0.0,
+                f32::MAX
+            )
+            .is_none());
+
+        let material = Arc::new(Lambertian::new(0.5, 0.5, 0.5));
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, -2.0), 0.5, material.clone());
+        let list = HitableList {
+            hitables: vec![Arc::new(sphere)],
+        };
+        // an empty list has no bounding box
+        let empty: Arc<dyn Hitable> = Arc::new(HitableList { hitables: vec![] });
+        let bvh = Bvh::new(
+            vec![
+                Arc::new(list),
+                empty,
+                Arc::new(Quad::xy(-1.0, 1.0, -1.0, 1.0, -5.0, material)),
+            ],
+            0.0,
+            0.0,
+        );
+        assert!(bvh.bounding_box(0.0, 0.0).is_none());
+        let r = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(bvh.hit(&r, 0.001, f32::MAX).unwrap().t, 1.5);
+        assert_eq!(bvh.hit(&r, 3.0, f32::MAX).unwrap().t, 5.0);
+    }
+}
@@ -1,6 +1,7 @@
+use crate::error::{Error, Result};
+use crate::random;
 use crate::ray::Ray;
 use crate::vec3::Vec3;
-use rand;
 use std::f32::consts::PI;
 
 #[derive(Debug, Clone, Copy)]
@@ -13,6 +14,9 @@ pub struct Camera {
     pub v: Vec3,
     pub w: Vec3,
     pub lens_radius: f32,
+    // Shutter open and close times. Rays are spread uniformly in between.
+    pub time0: f32,
+    pub time1: f32,
 }
 
 impl Camera {
@@ -46,26 +50,131 @@ impl Camera {
             v,
             w,
             lens_radius,
+            time0: 0.0,
+            time1: 0.0,
         }
     }
 
+    // Keep the shutter open from `time0` to `time1`, for motion blur
+    pub fn with_shutter(mut self, time0: f32, time1: f32) -> Self {
+        self.time0 = time0;
+        self.time1 = time1;
+        self
+    }
+
+    // Check that the camera defines a usable projection. `new` does not fail
+    // on bad parameters but produces NaNs or a flipped image plane.
+    pub fn validate(&self) -> Result<()> {
+        let finite = |v: &Vec3| v.x.is_finite() && v.y.is_finite() && v.z.is_finite();
+
+        if !finite(&self.origin) {
+            return Err(Error::InvalidCamera("lookfrom is not finite".to_string()));
+        }
+        if !(finite(&self.u) && finite(&self.v) && finite(&self.w)) {
+            return Err(Error::InvalidCamera(
+                "no view direction: lookat equals lookfrom or vup is parallel to the view"
+                    .to_string(),
+            ));
+        }
+        if !(finite(&self.lower_left_corner) && finite(&self.horizontal) && finite(&self.vertical))
+            || self.horizontal.dot(&self.u) <= 0.0
+            || self.vertical.dot(&self.v) <= 0.0
+        {
+            return Err(Error::InvalidCamera(
+                "vfov must be in (0, 180) and aspect and focus_dist must be positive".to_string(),
+            ));
+        }
+        if !(self.lens_radius >= 0.0 && self.lens_radius.is_finite()) {
+            return Err(Error::InvalidCamera(
+                "aperture must not be negative".to_string(),
+            ));
+        }
+        if !(self.time0.is_finite() && self.time1.is_finite() && self.time0 <= self.time1) {
+            return Err(Error::InvalidCamera(
+                "the shutter must close after it opens".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
     pub fn get_ray(&self, s: f32, t: f32) -> Ray {
         let rd: Vec3 = self.lens_radius * random_in_unit_disk();
         let offset: Vec3 = self.u * rd.x + self.v * rd.y;
-        Ray {
-            origin: self.origin + offset,
-            direction: self.lower_left_corner + s * self.horizontal + t * self.vertical
-                - self.origin
-                - offset,
-        }
+        // no random number is drawn for an instantaneous shutter
+        let time = if self.time1 > self.time0 {
+            self.time0 + random::next_f32() * (self.time1 - self.time0)
+        } else {
+            self.time0
+        };
+        Ray::with_time(
+            self.origin + offset,
+            self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
+            time,
+        )
     }
 }
 
 fn random_in_unit_disk() -> Vec3 {
     let mut p: Vec3 = Vec3::new(1.1, 1.1, 1.1);
     while p.squared_length() >= 1.0 {
-        p = 2.0 * Vec3::new(rand::random::<f32>(), rand::random::<f32>(), 0.0)
-            - Vec3::new(1.0, 1.0, 0.0);
+        p = 2.0 * Vec3::new(random::next_f32(), random::next_f32(), 0.0) - Vec3::new(1.0, 1.0, 0.0);
     }
     p
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_camera() {
+        let lookfrom = Vec3::new(0.0, 0.0, 1.0);
+        let lookat = Vec3::new(0.0, 0.0, 0.0);
+        let vup = Vec3::new(0.0, 1.0, 0.0);
+
+        assert!(Camera::new(lookfrom, lookat, vup, 90.0, 1.5, 0.1, 1.0)
+            .validate()
+            .is_ok());
+        assert!(Camera::new(lookfrom, lookfrom, vup, 90.0, 1.5, 0.1, 1.0)
+            .validate()
+            .is_err());
+        assert!(
+            Camera::new(lookfrom, lookat, -lookfrom, 90.0, 1.5, 0.1, 1.0)
+                .validate()
+                .is_err()
+        );
+        assert!(Camera::new(lookfrom, lookat, vup, 200.0, 1.5, 0.1, 1.0)
+            .validate()
+            .is_err());
+        assert!(Camera::new(lookfrom, lookat, vup, 90.0, -1.5, 0.1, 1.0)
+            .validate()
+            .is_err());
+        assert!(Camera::new(lookfrom, lookat, vup, 90.0, 1.5, -0.1, 1.0)
+            .validate()
+            .is_err());
+        let cam = Camera::new(lookfrom, lookat, vup, 90.0, 1.5, 0.1, 1.0);
+        assert!(cam.with_shutter(0.0, 1.0).validate().is_ok());
+        assert!(cam.with_shutter(1.0, 0.0).validate().is_err());
+    }
+
+    #[test]
+    fn rays_sample_the_shutter_interval() {
+        let cam = Camera::new(
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            90.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+        assert_eq!(cam.get_ray(0.5, 0.5).time, 0.0);
+
+        random::seed(4);
+        let cam = cam.with_shutter(2.0, 3.0);
+        let times: Vec<f32> = (0..1000).map(|_| cam.get_ray(0.5, 0.5).time).collect();
+        assert!(times.iter().all(|&t| (2.0..=3.0).contains(&t)));
+        let mean = times.iter().sum::<f32>() / times.len() as f32;
+        assert!((mean - 2.5).abs() < 0.05);
+    }
+}
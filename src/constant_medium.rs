@@ -0,0 +1,120 @@
+use crate::aabb::Aabb;
+use crate::hitable::{HitRecord, Hitable};
+use crate::material::{Isotropic, Material};
+use crate::random;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+use std::borrow::Borrow;
+use std::sync::Arc;
+
+// Volume of constant density filling a closed boundary, e.g. smoke or fog.
+// Rays scatter inside at exponentially distributed distances, with the
+// phase function of `phase` (usually `Isotropic`).
+pub struct ConstantMedium<H: Hitable> {
+    pub boundary: H,
+    // probability of scattering per unit length
+    pub density: f32,
+    pub phase: Arc<dyn Material>,
+}
+
+impl<H: Hitable> ConstantMedium<H> {
+    pub fn new(boundary: H, density: f32, phase: Arc<dyn Material>) -> Self {
+        ConstantMedium {
+            boundary,
+            density,
+            phase,
+        }
+    }
+
+    // Medium scattering `albedo` of the light uniformly in all directions
+    pub fn isotropic(boundary: H, density: f32, albedo: Vec3) -> Self {
+        ConstantMedium::new(boundary, density, Arc::new(Isotropic::new(albedo)))
+    }
+}
+
+impl<H: Hitable> Hitable for ConstantMedium<H> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        // Where the line of the ray enters and leaves the boundary. Looking
+        // behind the origin too finds the entry of rays starting inside.
+        let enter = self.boundary.hit(r, f32::NEG_INFINITY, f32::INFINITY)?;
+        let leave = self.boundary.hit(r, enter.t + 1e-4, f32::INFINITY)?;
+
+        let t0 = enter.t.max(t_min);
+        let t1 = leave.t.min(t_max);
+        if t0 >= t1 {
+            return None;
+        }
+
+        let length = r.direction.length();
+        let inside = (t1 - t0) * length;
+        let distance = -(1.0 - random::next_f32()).ln() / self.density;
+        if distance > inside {
+            return None;
+        }
+        let t = t0 + distance / length;
+        Some(HitRecord {
+            t,
+            p: r.point_at_parameter(t),
+            // there is no surface: any normal will do
+            normal: Vec3::new(1.0, 0.0, 0.0),
+            front_face: true,
+            u: 0.0,
+            v: 0.0,
+            dpdu: Vec3::new(0.0, 0.0, 0.0),
+            dpdv: Vec3::new(0.0, 0.0, 0.0),
+            material: self.phase.borrow(),
+        })
+    }
+
+    fn bounding_box(&self, time0: f32, time1: f32) -> Option<Aabb> {
+        self.boundary.bounding_box(time0, time1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+
+    fn fog(density: f32) -> ConstantMedium<Sphere> {
+        let boundary = Sphere::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            1.0,
+            Arc::new(Lambertian::new(0.5, 0.5, 0.5)),
+        );
+        ConstantMedium::isotropic(boundary, density, Vec3::new(1.0, 1.0, 1.0))
+    }
+
+    // Fraction of rays crossing the medium without scattering
+    fn transmittance(medium: &ConstantMedium<Sphere>, r: &Ray, t_max: f32) -> f32 {
+        const N: usize = 20_000;
+        let passed = (0..N)
+            .filter(|_| medium.hit(r, 0.001, t_max).is_none())
+            .count();
+        passed as f32 / N as f32
+    }
+
+    #[test]
+    fn exponential_transmittance() {
+        random::seed(6);
+        let medium = fog(0.5);
+        // a chord of length 2 from outside
+        let through = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -2.0));
+        assert!((transmittance(&medium, &through, f32::MAX) - (-1.0f32).exp()).abs() < 0.015);
+        // from the center, a length of 1 remains
+        let inside = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!((transmittance(&medium, &inside, f32::MAX) - (-0.5f32).exp()).abs() < 0.015);
+        // stopped by an object halfway
+        assert!((transmittance(&medium, &inside, 0.5) - (-0.25f32).exp()).abs() < 0.015);
+
+        for _ in 0..100 {
+            if let Some(rec) = medium.hit(&inside, 0.001, f32::MAX) {
+                assert!(rec.t > 0.001 && rec.t < 1.0);
+            }
+        }
+        let beside = Ray::new(Vec3::new(2.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(medium.hit(&beside, 0.001, f32::MAX).is_none());
+    }
+}
@@ -0,0 +1,109 @@
+// Rectangular sub-region of the image to render.
+//
+// Pixel coordinates follow the written image: `x` grows to the right and
+// `y` grows downwards from the top row, and the window covers
+// `x0..x1` x `y0..y1` (end exclusive).
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct CropWindow {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+}
+
+// What to write out when only a region has been rendered.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CropOutput {
+    // Only the pixels inside the window.
+    Cropped,
+    // The whole frame, black outside the window.
+    FullFrame,
+}
+
+impl CropWindow {
+    pub fn new(x0: u32, y0: u32, x1: u32, y1: u32) -> Self {
+        CropWindow { x0, y0, x1, y1 }
+    }
+
+    // Window covering the whole `nx` x `ny` image
+    pub fn full(nx: u32, ny: u32) -> Self {
+        CropWindow::new(0, 0, nx, ny)
+    }
+
+    // Make a window from coordinates normalized to [0, 1] over the image.
+    // Partially covered pixels are included.
+    pub fn from_normalized(x0: f32, y0: f32, x1: f32, y1: f32, nx: u32, ny: u32) -> Self {
+        let to_pixel = |t: f32, n: u32, round: fn(f32) -> f32| -> u32 {
+            round(t.clamp(0.0, 1.0) * n as f32) as u32
+        };
+        CropWindow::new(
+            to_pixel(x0.min(x1), nx, f32::floor),
+            to_pixel(y0.min(y1), ny, f32::floor),
+            to_pixel(x0.max(x1), nx, f32::ceil),
+            to_pixel(y0.max(y1), ny, f32::ceil),
+        )
+    }
+
+    // Restrict the window to an `nx` x `ny` image
+    pub fn clamp(&self, nx: u32, ny: u32) -> Self {
+        let x0 = self.x0.min(nx);
+        let y0 = self.y0.min(ny);
+        CropWindow::new(x0, y0, self.x1.min(nx).max(x0), self.y1.min(ny).max(y0))
+    }
+
+    pub fn width(&self) -> u32 {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> u32 {
+        self.y1 - self.y0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width() == 0 || self.height() == 0
+    }
+
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        self.x0 <= x && x < self.x1 && self.y0 <= y && y < self.y1
+    }
+
+    // Pixels of the window in the order they are written, top row first.
+    pub fn pixels(&self) -> Vec<(u32, u32)> {
+        let mut v: Vec<(u32, u32)> = Vec::with_capacity((self.width() * self.height()) as usize);
+        for y in self.y0..self.y1 {
+            for x in self.x0..self.x1 {
+                v.push((x, y));
+            }
+        }
+        v
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalized_window_covers_partial_pixels() {
+        let crop = CropWindow::from_normalized(0.25, 0.1, 0.5, 0.55, 400, 300);
+        assert_eq!(crop, CropWindow::new(100, 30, 200, 165));
+
+        let crop = CropWindow::from_normalized(0.501, 0.0, 0.502, 1.0, 400, 300);
+        assert_eq!(crop, CropWindow::new(200, 0, 201, 300));
+    }
+
+    #[test]
+    fn clamp_to_image() {
+        let crop = CropWindow::new(350, 280, 500, 400).clamp(400, 300);
+        assert_eq!(crop, CropWindow::new(350, 280, 400, 300));
+        assert!(CropWindow::new(500, 0, 600, 10).clamp(400, 300).is_empty());
+    }
+
+    #[test]
+    fn pixels_in_write_order() {
+        let crop = CropWindow::new(1, 2, 3, 4);
+        assert_eq!(crop.pixels(), vec![(1, 2), (2, 2), (1, 3), (2, 3)]);
+        assert!(crop.contains(2, 3));
+        assert!(!crop.contains(3, 3));
+    }
+}
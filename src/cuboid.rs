@@ -0,0 +1,90 @@
+use crate::aabb::Aabb;
+use crate::hitable::{HitRecord, Hitable};
+use crate::hitable_list::HitableList;
+use crate::material::Material;
+use crate::quad::Quad;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+use std::sync::Arc;
+
+// Axis-aligned box made of six quads facing outwards
+pub struct Cuboid {
+    pub min: Vec3,
+    pub max: Vec3,
+    sides: HitableList,
+}
+
+impl Cuboid {
+    // Box with `a` and `b` as opposite corners, in any order
+    pub fn new(a: Vec3, b: Vec3, material: Arc<dyn Material>) -> Self {
+        let min = a.min(&b);
+        let max = a.max(&b);
+        let (x0, y0, z0) = (min.x, min.y, min.z);
+        let (x1, y1, z1) = (max.x, max.y, max.z);
+        let m = || material.clone();
+
+        let sides: Vec<Arc<dyn Hitable>> = vec![
+            Arc::new(Quad::xy(x0, x1, y0, y1, z1, m())),
+            Arc::new(Quad::xy(x0, x1, y0, y1, z0, m()).flipped()),
+            Arc::new(Quad::xz(x0, x1, z0, z1, y1, m())),
+            Arc::new(Quad::xz(x0, x1, z0, z1, y0, m()).flipped()),
+            Arc::new(Quad::yz(y0, y1, z0, z1, x1, m())),
+            Arc::new(Quad::yz(y0, y1, z0, z1, x0, m()).flipped()),
+        ];
+        Cuboid {
+            min,
+            max,
+            sides: HitableList { hitables: sides },
+        }
+    }
+}
+
+impl Hitable for Cuboid {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.sides.hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32) -> Option<Aabb> {
+        Some(Aabb::new(self.min, self.max))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn normals_face_outwards() {
+        let cuboid = Cuboid::new(
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(-1.0, -2.0, -1.0),
+            Arc::new(Lambertian::new(0.5, 0.5, 0.5)),
+        );
+        let axes = [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ];
+        for &axis in &axes {
+            for &side in &[axis, -axis] {
+                // from outside towards the center
+                let r = Ray::new(side * 5.0, -side);
+                let rec = cuboid.hit(&r, 0.0, f32::MAX).unwrap();
+                assert!(rec.front_face);
+                assert_eq!(rec.normal, side);
+                // from the center outwards: the far side, from the back
+                let r = Ray::new(Vec3::new(0.0, -0.5, 0.0), side);
+                let rec = cuboid.hit(&r, 0.0, f32::MAX).unwrap();
+                assert!(!rec.front_face);
+                assert_eq!(rec.normal, -side);
+                assert_eq!(rec.outward_normal(), side);
+            }
+        }
+
+        let bbox = cuboid.bounding_box(0.0, 0.0).unwrap();
+        assert_eq!(bbox.min, Vec3::new(-1.0, -2.0, -1.0));
+        assert_eq!(bbox.max, Vec3::new(1.0, 1.0, 1.0));
+    }
+}
@@ -0,0 +1,70 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Crate-wide error type
+#[derive(Debug)]
+pub enum Error {
+    // Reading or writing failed. `path` is the file involved, when there is one.
+    Io {
+        path: Option<PathBuf>,
+        source: io::Error,
+    },
+    // Malformed input: command line arguments, image files, ...
+    Parse(String),
+    // The scene cannot be rendered, e.g. a degenerate primitive
+    InvalidScene(String),
+    // The camera does not define a valid projection
+    InvalidCamera(String),
+    // The render settings are unusable, e.g. an empty image
+    InvalidSettings(String),
+    // Images that cannot be used together, e.g. compared with different sizes
+    InvalidImage(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    // Make a function tagging an I/O error with the file it happened on,
+    // to be used with `map_err`.
+    pub fn at<P: AsRef<Path>>(path: P) -> impl FnOnce(io::Error) -> Error {
+        let path = path.as_ref().to_path_buf();
+        move |source| Error::Io {
+            path: Some(path),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io {
+                path: Some(path),
+                source,
+            } => write!(f, "{}: {}", path.display(), source),
+            Error::Io { path: None, source } => write!(f, "I/O error: {}", source),
+            Error::Parse(msg) => write!(f, "parse error: {}", msg),
+            Error::InvalidScene(msg) => write!(f, "invalid scene: {}", msg),
+            Error::InvalidCamera(msg) => write!(f, "invalid camera: {}", msg),
+            Error::InvalidSettings(msg) => write!(f, "invalid render settings: {}", msg),
+            Error::InvalidImage(msg) => write!(f, "invalid image: {}", msg),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Self {
+        Error::Io { path: None, source }
+    }
+}
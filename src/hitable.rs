@@ -1,16 +1,84 @@
+use crate::aabb::Aabb;
 use crate::material::Material;
 use crate::ray::Ray;
 use crate::vec3::Vec3;
 
+use std::sync::Arc;
+
 #[derive(Clone, Copy)]
 pub struct HitRecord<'a> {
     pub t: f32,
     pub p: Vec3,
+    // Unit normal on the side the ray comes from
     pub normal: Vec3,
+    // Whether the ray hits the side the geometric normal of the surface points
+    // to, the outside of closed objects
+    pub front_face: bool,
+    // Surface coordinates of the hit point in [0, 1], for texturing
+    pub u: f32,
+    pub v: f32,
+    // Derivatives of the hit point along `u` and `v`, tangent to the surface
+    // and not normalized. Zero where there is no surface.
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
     pub material: &'a dyn Material,
 }
 
+impl<'a> HitRecord<'a> {
+    // Orient the normal against `r`, for a surface whose geometric normal at
+    // the hit point is `outward_normal`
+    pub fn set_face_normal(&mut self, r: &Ray, outward_normal: Vec3) {
+        self.front_face = r.direction.dot(&outward_normal) < 0.0;
+        self.normal = if self.front_face {
+            outward_normal
+        } else {
+            -outward_normal
+        };
+    }
+
+    // Hit at the origin of the front of a surface facing +z, with `u` along x
+    // and `v` along y: the local frame where materials are tested and
+    // measured
+    pub fn facing_z(material: &'a dyn Material) -> Self {
+        HitRecord {
+            t: 1.0,
+            p: Vec3::new(0.0, 0.0, 0.0),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            front_face: true,
+            u: 0.0,
+            v: 0.0,
+            dpdu: Vec3::new(1.0, 0.0, 0.0),
+            dpdv: Vec3::new(0.0, 1.0, 0.0),
+            material,
+        }
+    }
+
+    // Geometric normal of the surface, whichever side the ray comes from
+    pub fn outward_normal(&self) -> Vec3 {
+        if self.front_face {
+            self.normal
+        } else {
+            -self.normal
+        }
+    }
+}
+
 // `Hitalbe` trait needs `Send` and `Sync` for `rayon` parallel processing.
 pub trait Hitable: Send + Sync {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>>;
+
+    // Box containing the object at all times between `time0` and `time1`,
+    // or `None` if it is unbounded
+    fn bounding_box(&self, time0: f32, time1: f32) -> Option<Aabb>;
+}
+
+// Shared objects, e.g. the same geometry placed several times
+impl<H: Hitable + ?Sized> Hitable for Arc<H> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        (**self).hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self, time0: f32, time1: f32) -> Option<Aabb> {
+        (**self).bounding_box(time0, time1)
+    }
 }
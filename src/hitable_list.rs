@@ -1,15 +1,18 @@
+use crate::aabb::Aabb;
 use crate::hitable::{HitRecord, Hitable};
 use crate::ray::Ray;
+use crate::stats;
 use std::sync::Arc;
 
 pub struct HitableList {
-    pub hitables: Vec<Arc<Hitable>>,
+    pub hitables: Vec<Arc<dyn Hitable>>,
 }
 
 impl Hitable for HitableList {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let mut closest_so_far: f32 = t_max;
         let mut hit_anything: Option<HitRecord> = None;
+        stats::add_intersection_tests(self.hitables.len() as u64);
         for hitable in &self.hitables {
             if let Some(hit_record) = hitable.hit(r, t_min, closest_so_far) {
                 closest_so_far = hit_record.t;
@@ -18,4 +21,10 @@ impl Hitable for HitableList {
         }
         hit_anything
     }
+
+    fn bounding_box(&self, time0: f32, time1: f32) -> Option<Aabb> {
+        let mut boxes = self.hitables.iter().map(|h| h.bounding_box(time0, time1));
+        let first = boxes.next()??;
+        boxes.try_fold(first, |acc, b| Some(acc.surrounding(&b?)))
+    }
 }
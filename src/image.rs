@@ -0,0 +1,378 @@
+use crate::error::{Error, Result};
+use crate::vec3::Vec3;
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+// In-memory image of linear colors. Pixels are stored row by row, top row first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Vec3>,
+}
+
+impl Image {
+    // Make a black image
+    pub fn new(width: u32, height: u32) -> Self {
+        Image {
+            width,
+            height,
+            pixels: vec![Vec3::new(0.0, 0.0, 0.0); (width * height) as usize],
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Vec3 {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, color: Vec3) {
+        self.pixels[(y * self.width + x) as usize] = color;
+    }
+
+    // Copy `other` into this image with its top left corner at (x0, y0).
+    // Pixels falling outside of this image are dropped.
+    pub fn paste(&mut self, other: &Image, x0: u32, y0: u32) {
+        for y in 0..other.height.min(self.height.saturating_sub(y0)) {
+            for x in 0..other.width.min(self.width.saturating_sub(x0)) {
+                self.set(x0 + x, y0 + y, other.get(x, y));
+            }
+        }
+    }
+
+    // Write as plain (P3) PPM, gamma corrected with gamma 2
+    pub fn write_ppm<W: Write>(&self, w: &mut W) -> Result<()> {
+        Ok(self.encode_ppm(w)?)
+    }
+
+    pub fn save_ppm<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut f = BufWriter::new(fs::File::create(path).map_err(Error::at(path))?);
+        self.encode_ppm(&mut f)
+            .and_then(|_| f.flush())
+            .map_err(Error::at(path))
+    }
+
+    // Read a plain (P3) or binary (P6) PPM, undoing the gamma 2 of `write_ppm`
+    pub fn read_ppm<R: Read>(r: &mut R) -> Result<Image> {
+        let mut data: Vec<u8> = vec![];
+        r.read_to_end(&mut data)?;
+        decode_ppm(&data)
+    }
+
+    pub fn load_ppm<P: AsRef<Path>>(path: P) -> Result<Image> {
+        let path = path.as_ref();
+        let data = fs::read(path).map_err(Error::at(path))?;
+        decode_ppm(&data).map_err(|e| match e {
+            Error::Parse(msg) => Error::Parse(format!("{}: {}", path.display(), msg)),
+            e => e,
+        })
+    }
+
+    // Load a PPM, PNG or OpenEXR file, depending on the file extension
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Image> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("ppm") => Image::load_ppm(path),
+            Some("png") => Image::load_png(path),
+            Some("exr") => Image::load_exr(path),
+            _ => Err(Error::Parse(format!(
+                "{}: unknown image format, expected a .ppm, .png or .exr file",
+                path.display()
+            ))),
+        }
+    }
+
+    // Read an 8 or 16 bit PNG, converting from sRGB to linear. Alpha is ignored.
+    pub fn load_png<P: AsRef<Path>>(path: P) -> Result<Image> {
+        let path = path.as_ref();
+        let file = fs::File::open(path).map_err(Error::at(path))?;
+        decode_png(BufReader::new(file)).map_err(|e| match e {
+            png::DecodingError::IoError(source) => Error::at(path)(source),
+            e => Error::Parse(format!("{}: {}", path.display(), e)),
+        })
+    }
+
+    // Read the first RGB layer of an OpenEXR file. Alpha is ignored.
+    pub fn load_exr<P: AsRef<Path>>(path: P) -> Result<Image> {
+        let path = path.as_ref();
+        let exr_image = exr::prelude::read_first_rgba_layer_from_file(
+            path,
+            |size, _| Image::new(size.width() as u32, size.height() as u32),
+            |img: &mut Image, pos, (r, g, b, _): (f32, f32, f32, f32)| {
+                img.set(pos.x() as u32, pos.y() as u32, Vec3::new(r, g, b))
+            },
+        )
+        .map_err(|e| match e {
+            exr::error::Error::Io(source) => Error::at(path)(source),
+            e => Error::Parse(format!("{}: {}", path.display(), e)),
+        })?;
+        Ok(exr_image.layer_data.channel_data.pixels)
+    }
+
+    fn encode_ppm<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(format!("P3\n{} {}\n255\n", self.width, self.height).as_bytes())?;
+        for pix in &self.pixels {
+            let ir = to_byte(pix.x);
+            let ig = to_byte(pix.y);
+            let ib = to_byte(pix.z);
+            w.write_all(format!("{} {} {}\n", ir, ig, ib).as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+fn to_byte(c: f32) -> u8 {
+    (255.99 * c.max(0.0).sqrt()).min(255.0) as u8
+}
+
+// Cursor over the bytes of a PPM file
+struct PpmReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PpmReader<'a> {
+    // Next whitespace separated token, skipping `#` comments
+    fn token(&mut self) -> Result<&'a str> {
+        loop {
+            match self.data.get(self.pos) {
+                Some(b'#') => {
+                    while !matches!(self.data.get(self.pos), Some(b'\n') | None) {
+                        self.pos += 1;
+                    }
+                }
+                Some(c) if c.is_ascii_whitespace() => self.pos += 1,
+                Some(_) => break,
+                None => return Err(Error::Parse("unexpected end of file".to_string())),
+            }
+        }
+        let start = self.pos;
+        while matches!(self.data.get(self.pos), Some(c) if !c.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.data[start..self.pos])
+            .map_err(|_| Error::Parse("invalid header".to_string()))
+    }
+
+    fn number(&mut self) -> Result<u32> {
+        let token = self.token()?;
+        token
+            .parse::<u32>()
+            .map_err(|_| Error::Parse(format!("expected a number, got '{}'", token)))
+    }
+
+    // Next binary sample of `bytes` bytes, most significant first
+    fn binary(&mut self, bytes: usize) -> Result<u32> {
+        let end = self.pos + bytes;
+        let sample = self
+            .data
+            .get(self.pos..end)
+            .ok_or_else(|| Error::Parse("unexpected end of file".to_string()))?
+            .iter()
+            .fold(0, |acc, &b| (acc << 8) | b as u32);
+        self.pos = end;
+        Ok(sample)
+    }
+}
+
+fn decode_ppm(data: &[u8]) -> Result<Image> {
+    let mut r = PpmReader { data, pos: 0 };
+    let magic = r.token()?;
+    let binary = match magic {
+        "P3" => false,
+        "P6" => true,
+        _ => {
+            return Err(Error::Parse(format!(
+                "not a P3 or P6 PPM file ('{}')",
+                magic
+            )))
+        }
+    };
+    let width = r.number()?;
+    let height = r.number()?;
+    let maxval = r.number()?;
+    if maxval == 0 || maxval > 65535 {
+        return Err(Error::Parse(format!("invalid maximum value {}", maxval)));
+    }
+    // a single whitespace separates the header from binary data
+    r.pos += 1;
+
+    let bytes = if maxval < 256 { 1 } else { 2 };
+    // Headers are checked against the data before allocating the image: a
+    // few bytes could otherwise ask for gigabytes.
+    let samples = width
+        .checked_mul(height)
+        .and_then(|n| n.checked_mul(3))
+        .ok_or_else(|| Error::Parse(format!("image too large ({}x{})", width, height)))?
+        as usize;
+    // at least one digit per sample in plain files
+    let needed = if binary { samples * bytes } else { samples };
+    if r.data.len().saturating_sub(r.pos) < needed {
+        return Err(Error::Parse(format!(
+            "not enough pixel data for {}x{}",
+            width, height
+        )));
+    }
+    let mut sample = || -> Result<f32> {
+        let value = if binary {
+            r.binary(bytes)?
+        } else {
+            r.number()?
+        };
+        let c = value.min(maxval) as f32 / maxval as f32;
+        Ok(c * c)
+    };
+    let mut img = Image::new(width, height);
+    for pix in img.pixels.iter_mut() {
+        *pix = Vec3::new(sample()?, sample()?, sample()?);
+    }
+    Ok(img)
+}
+
+fn decode_png<R: Read>(r: R) -> std::result::Result<Image, png::DecodingError> {
+    let mut decoder = png::Decoder::new(r);
+    // palette and low bit depths to 8 bit samples
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+
+    let channels = info.color_type.samples();
+    let bytes = if info.bit_depth == png::BitDepth::Sixteen {
+        2
+    } else {
+        1
+    };
+    let maxval = ((1u32 << (8 * bytes)) - 1) as f32;
+    let sample = |row: &[u8], i: usize| -> f32 {
+        let value = if bytes == 2 {
+            u16::from_be_bytes([row[2 * i], row[2 * i + 1]]) as f32
+        } else {
+            row[i] as f32
+        };
+        srgb_to_linear(value / maxval)
+    };
+
+    let mut img = Image::new(info.width, info.height);
+    for (y, row) in buf
+        .chunks(info.line_size)
+        .take(info.height as usize)
+        .enumerate()
+    {
+        for x in 0..info.width {
+            let i = x as usize * channels;
+            let color = if channels < 3 {
+                let gray = sample(row, i);
+                Vec3::new(gray, gray, gray)
+            } else {
+                Vec3::new(sample(row, i), sample(row, i + 1), sample(row, i + 2))
+            };
+            img.set(x, y as u32, color);
+        }
+    }
+    Ok(img)
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.040_45 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_gamma_corrected_ppm() {
+        let mut img = Image::new(2, 1);
+        img.set(0, 0, Vec3::new(0.25, 1.0, 4.0));
+        let mut buf: Vec<u8> = vec![];
+        img.write_ppm(&mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "P3\n2 1\n255\n127 255 255\n0 0 0\n"
+        );
+    }
+
+    #[test]
+    fn read_ppm() {
+        let plain = "P3\n# comment\n2 1\n255\n255 0 127\n0 255 0\n";
+        let img = Image::read_ppm(&mut plain.as_bytes()).unwrap();
+        assert_eq!((img.width, img.height), (2, 1));
+        assert_eq!(img.get(0, 0).x, 1.0);
+        assert!((img.get(0, 0).z - 0.25).abs() < 0.01);
+
+        let mut binary = b"P6 2 1 255\n".to_vec();
+        binary.extend_from_slice(&[255, 0, 127, 0, 255, 0]);
+        assert_eq!(Image::read_ppm(&mut binary.as_slice()).unwrap(), img);
+
+        assert!(Image::read_ppm(&mut "P3\n2 1\n255\n1 2 3\n".as_bytes()).is_err());
+        assert!(Image::read_ppm(&mut "P5\n2 1\n255\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn reject_oversized_ppm() {
+        // overflows the pixel count
+        let header = b"P6 65536 65536 255\n";
+        assert!(matches!(
+            Image::read_ppm(&mut &header[..]),
+            Err(Error::Parse(_))
+        ));
+        // would take gigabytes for a few bytes of data
+        let mut data = b"P6 40000 40000 255\n".to_vec();
+        data.extend_from_slice(&[0; 30]);
+        assert!(matches!(
+            Image::read_ppm(&mut data.as_slice()),
+            Err(Error::Parse(_))
+        ));
+        assert!(Image::read_ppm(&mut "P3 40000 40000 255\n0 0 0\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn read_png() {
+        let mut data: Vec<u8> = vec![];
+        {
+            let mut encoder = png::Encoder::new(&mut data, 2, 1);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&[255, 0, 188, 0, 255, 0]).unwrap();
+        }
+        let img = decode_png(data.as_slice()).unwrap();
+        assert_eq!((img.width, img.height), (2, 1));
+        assert_eq!(img.get(0, 0).x, 1.0);
+        assert_eq!(img.get(0, 0).y, 0.0);
+        // sRGB 188 is about half the linear intensity of white
+        assert!((img.get(0, 0).z - 0.5).abs() < 0.01);
+        assert_eq!(img.get(1, 0), Vec3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn read_exr() {
+        let path = std::env::temp_dir().join(format!("rtow-read-exr-{}.exr", std::process::id()));
+        exr::prelude::write_rgb_file(&path, 3, 2, |x, y| (x as f32, y as f32, 2.5)).unwrap();
+        let img = Image::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((img.width, img.height), (3, 2));
+        assert_eq!(img.get(2, 1), Vec3::new(2.0, 1.0, 2.5));
+    }
+
+    #[test]
+    fn paste_clips_to_image() {
+        let mut small = Image::new(2, 2);
+        small.pixels = vec![Vec3::new(1.0, 1.0, 1.0); 4];
+        let mut img = Image::new(3, 3);
+        img.paste(&small, 2, 1);
+        assert_eq!(img.get(2, 1), Vec3::new(1.0, 1.0, 1.0));
+        assert_eq!(img.get(2, 2), Vec3::new(1.0, 1.0, 1.0));
+        assert_eq!(img.get(1, 1), Vec3::new(0.0, 0.0, 0.0));
+    }
+}
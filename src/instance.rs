@@ -0,0 +1,196 @@
+use crate::aabb::Aabb;
+use crate::animated_transform::AnimatedTransform;
+use crate::hitable::{HitRecord, Hitable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::transform::Transform;
+use crate::transformed::hit_transformed;
+
+use std::borrow::Borrow;
+use std::sync::Arc;
+
+// A placement of shared geometry, e.g. one tree of a forest. The geometry,
+// typically a `Bvh`, is referenced and not copied, so memory grows with the
+// number of distinct geometries rather than with the number of instances.
+// Put the instances in a `Bvh` to get a two-level hierarchy.
+//
+// The transform can be animated with keyframes, for the motion blur of
+// spinning or moving objects.
+pub struct Instance {
+    geometry: Arc<dyn Hitable>,
+    motion: AnimatedTransform,
+    // replaces the materials of the geometry when set
+    material: Option<Arc<dyn Material>>,
+}
+
+impl Instance {
+    pub fn new(geometry: Arc<dyn Hitable>, transform: Transform) -> Self {
+        Instance::animated(geometry, AnimatedTransform::fixed(transform))
+    }
+
+    pub fn animated(geometry: Arc<dyn Hitable>, motion: AnimatedTransform) -> Self {
+        Instance {
+            geometry,
+            motion,
+            material: None,
+        }
+    }
+
+    // Render the whole geometry with `material`. A `Masked` material cuts
+    // holes at world-space points, where it is shaded; cutouts of the own
+    // materials of the geometry are part of its shape and stay, moving with
+    // it.
+    pub fn with_material(mut self, material: Arc<dyn Material>) -> Self {
+        self.material = Some(material);
+        self
+    }
+
+    pub fn geometry(&self) -> &Arc<dyn Hitable> {
+        &self.geometry
+    }
+
+    pub fn transform_at(&self, time: f32) -> Transform {
+        self.motion.at(time)
+    }
+}
+
+impl Hitable for Instance {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let transform = self.motion.at(r.time);
+        let mut t_min = t_min;
+        loop {
+            let mut rec = hit_transformed(self.geometry.as_ref(), &transform, r, t_min, t_max)?;
+            let material = match &self.material {
+                Some(material) => material,
+                None => return Some(rec),
+            };
+            rec.material = material.borrow();
+            if material.alpha_test(&rec) {
+                return Some(rec);
+            }
+            // through a hole of the override, look further along the ray
+            t_min = rec.t;
+        }
+    }
+
+    fn bounding_box(&self, time0: f32, time1: f32) -> Option<Aabb> {
+        let b = self.geometry.bounding_box(time0, time1)?;
+        Some(self.motion.bounding_box(&b, time0, time1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::Bvh;
+    use crate::hitable_list::HitableList;
+    use crate::material::{AlphaMode, Lambertian, Masked, Metal};
+    use crate::sphere::Sphere;
+    use crate::texture::Texture;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn instances_share_geometry() {
+        let green = Arc::new(Lambertian::new(0.1, 0.8, 0.1));
+        let geometry: Arc<dyn Hitable> = Arc::new(Bvh::new(
+            vec![
+                Arc::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 0.5, green.clone())),
+                Arc::new(Sphere::new(Vec3::new(0.0, 0.3, 0.0), 0.3, green)),
+            ],
+            0.0,
+            0.0,
+        ));
+        let gold: Arc<dyn Material> = Arc::new(Metal::new((0.9, 0.7, 0.2), 0.0));
+
+        let mut instances: Vec<Arc<dyn Hitable>> = vec![];
+        for i in 0..100 {
+            let at = Vec3::new((i % 10) as f32 * 2.0, 0.0, -((i / 10) as f32) * 2.0);
+            let mut instance = Instance::new(geometry.clone(), Transform::translate(at));
+            if i == 42 {
+                instance = instance.with_material(gold.clone());
+            }
+            instances.push(Arc::new(instance));
+        }
+        assert_eq!(Arc::strong_count(&geometry), 101);
+        let top_level = Bvh::new(instances.clone(), 0.0, 0.0);
+        let list = HitableList {
+            hitables: instances,
+        };
+
+        // the same hits as testing every instance
+        for i in 0..100 {
+            let at = Vec3::new((i % 10) as f32 * 2.0, 1.0, -((i / 10) as f32) * 2.0);
+            let r = Ray::new(at + Vec3::new(0.0, 0.0, 0.1), Vec3::new(0.0, -1.0, 0.05));
+            let rec = top_level.hit(&r, 0.001, f32::MAX).unwrap();
+            assert_eq!(Some(rec.t), list.hit(&r, 0.001, f32::MAX).map(|rec| rec.t));
+
+            let is_gold = std::ptr::eq(
+                rec.material as *const dyn Material as *const u8,
+                gold.as_ref() as *const dyn Material as *const u8,
+            );
+            assert_eq!(is_gold, i == 42);
+        }
+    }
+
+    #[test]
+    fn keyframed_instance() {
+        let material = Arc::new(Lambertian::new(0.5, 0.5, 0.5));
+        let geometry: Arc<dyn Hitable> =
+            Arc::new(Sphere::new(Vec3::new(2.0, 0.0, 0.0), 0.5, material));
+        // the sphere turns around the y axis, from +x at time 0 to -z at time 1
+        let axis = Vec3::new(0.0, 1.0, 0.0);
+        let motion = AnimatedTransform::new(vec![
+            (0.0, Transform::identity()),
+            (1.0, Transform::rotate(axis, 90.0)),
+        ])
+        .unwrap();
+        let instance = Instance::animated(geometry, motion);
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        let at = |p: Vec3, time: f32| Ray::with_time(p + Vec3::new(0.0, 5.0, 0.0), down, time);
+
+        assert!(instance
+            .hit(&at(Vec3::new(2.0, 0.0, 0.0), 0.0), 0.001, f32::MAX)
+            .is_some());
+        assert!(instance
+            .hit(&at(Vec3::new(2.0, 0.0, 0.0), 1.0), 0.001, f32::MAX)
+            .is_none());
+        assert!(instance
+            .hit(&at(Vec3::new(0.0, 0.0, -2.0), 1.0), 0.001, f32::MAX)
+            .is_some());
+        let h = std::f32::consts::SQRT_2;
+        let rec = instance
+            .hit(&at(Vec3::new(h, 0.0, -h), 0.5), 0.001, f32::MAX)
+            .unwrap();
+        assert!((rec.p - Vec3::new(h, 0.5, -h)).length() < 1e-4);
+
+        let bbox = instance.bounding_box(0.0, 1.0).unwrap();
+        assert!(bbox.max.x >= 2.5 && bbox.min.z <= -2.5);
+        assert!(bbox.min.x < 0.0 && bbox.max.z > 0.0);
+    }
+
+    // Cut out above `z`, in world space
+    struct Above(f32);
+
+    impl Texture for Above {
+        fn value(&self, _u: f32, _v: f32, p: &Vec3) -> Vec3 {
+            let alpha = if p.z > self.0 { 0.0 } else { 1.0 };
+            Vec3::new(alpha, alpha, alpha)
+        }
+    }
+
+    #[test]
+    fn masked_material_override() {
+        let material = Arc::new(Lambertian::new(0.5, 0.5, 0.5));
+        let geometry: Arc<dyn Hitable> =
+            Arc::new(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, material.clone()));
+        let masked = Masked::new(material, Above(10.5), AlphaMode::Cutout(0.5));
+        let instance = Instance::new(geometry, Transform::translate(Vec3::new(0.0, 0.0, 10.0)))
+            .with_material(Arc::new(masked));
+
+        // the near side is cut out, the far side is not
+        let r = Ray::new(Vec3::new(0.0, 0.0, 20.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = instance.hit(&r, 0.001, f32::MAX).unwrap();
+        assert!((rec.p.z - 9.0).abs() < 1e-4, "{:?}", rec.p);
+        assert!(!rec.front_face);
+    }
+}
@@ -1,8 +1,37 @@
+pub mod aabb;
+pub mod animated_transform;
+pub mod bench;
+pub mod bvh;
 pub mod camera;
+pub mod constant_medium;
+pub mod crop;
+pub mod cuboid;
+pub mod error;
 pub mod hitable;
 pub mod hitable_list;
+pub mod image;
+pub mod instance;
 pub mod material;
+pub mod medium_stack;
+pub mod metrics;
+pub mod microfacet;
+pub mod moving_sphere;
+pub mod normal_map;
+pub mod perlin;
+pub mod principled;
+pub mod progress;
+pub mod quad;
+pub mod quaternion;
+pub mod random;
 pub mod ray;
+pub mod renderer;
+pub mod scene;
+pub mod spectrum;
 pub mod sphere;
+pub mod stats;
+pub mod texture;
+pub mod thin_film;
+pub mod transform;
+pub mod transformed;
 pub mod vec3;
-pub mod scene;
+pub mod volume;
@@ -1,107 +1,248 @@
-use rand;
-use rayon::prelude::*;
+use std::env;
 use std::fs;
-use std::io::{BufWriter, Write};
+use std::process::{self, Command};
 use std::time::Instant;
 
-mod camera;
-mod hitable;
-mod hitable_list;
-mod material;
-mod ray;
-mod scene;
-mod sphere;
-mod vec3;
-
-use camera::Camera;
-use hitable::Hitable;
-use hitable_list::HitableList;
-use ray::Ray;
-use vec3::Vec3;
-
-fn color(r: &Ray, world: &HitableList, depth: u32) -> Vec3 {
-    if let Some(rec) = world.hit(&r, 0.001, std::f32::MAX) {
-        if let Some(scatter_record) = rec.material.scatter(&r, &rec) {
-            if depth < 50 {
-                let attenuation: Vec3 = scatter_record.attenuation;
-                let scattered: Ray = scatter_record.scattered;
-                attenuation * color(&scattered, &world, depth + 1)
-            } else {
-                Vec3::new(0.0, 0.0, 0.0)
+use rust_rtow::bench::{self, BenchOptions};
+use rust_rtow::bvh::Bvh;
+use rust_rtow::crop::{CropOutput, CropWindow};
+use rust_rtow::error::{Error, Result};
+use rust_rtow::image::Image;
+use rust_rtow::metrics;
+use rust_rtow::progress::ProgressBar;
+use rust_rtow::renderer::{RenderSettings, Renderer};
+use rust_rtow::scene;
+
+const USAGE: &str = "usage: rust-rtow [--crop X0,Y0,X1,Y1 | --crop-norm X0,Y0,X1,Y1] [--full-frame]
+                 [--spectral]
+       rust-rtow bench [--quick] [--out FILE]
+       rust-rtow imgdiff IMAGE REFERENCE [--diff FILE] [--scale S]
+
+  --crop       render only the pixels X0..X1, Y0..Y1 (Y counted from the top row)
+  --crop-norm  same, with coordinates normalized to [0, 1]
+  --full-frame write the whole frame with black outside the region
+               instead of only the cropped pixels
+  --spectral   trace wavelengths instead of RGB, for dispersion
+
+  bench        run the benchmark suite and save the results as JSON
+  --quick      short runs, to check that the suite works
+  --out        JSON file to write (default: bench.json)
+
+  imgdiff      print the MSE, PSNR, SSIM and relative MSE of IMAGE against
+               REFERENCE, both PPM, PNG or OpenEXR files
+  --diff       false-color difference image to write (default: diff.ppm)
+  --scale      difference shown white (default: the largest difference)";
+
+fn parse_window(option: &str, arg: Option<String>) -> Result<[f32; 4]> {
+    let arg = arg.ok_or_else(|| Error::Parse(format!("{} needs a value", option)))?;
+    let invalid = || Error::Parse(format!("{} expects X0,Y0,X1,Y1, got '{}'", option, arg));
+    let values: Vec<f32> = arg
+        .split(',')
+        .map(|s| s.trim().parse::<f32>())
+        .collect::<std::result::Result<_, _>>()
+        .map_err(|_| invalid())?;
+    if values.len() == 4 {
+        Ok([values[0], values[1], values[2], values[3]])
+    } else {
+        Err(invalid())
+    }
+}
+
+fn parse_args<I: Iterator<Item = String>>(
+    mut args: I,
+    nx: u32,
+    ny: u32,
+) -> Result<(CropWindow, CropOutput, bool)> {
+    let mut crop = CropWindow::full(nx, ny);
+    let mut output = CropOutput::Cropped;
+    let mut spectral = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--crop" => {
+                let w = parse_window(&arg, args.next())?;
+                crop = CropWindow::new(w[0] as u32, w[1] as u32, w[2] as u32, w[3] as u32);
+            }
+            "--crop-norm" => {
+                let w = parse_window(&arg, args.next())?;
+                crop = CropWindow::from_normalized(w[0], w[1], w[2], w[3], nx, ny);
             }
-        } else {
-            Vec3::new(0.0, 0.0, 0.0)
+            "--full-frame" => output = CropOutput::FullFrame,
+            "--spectral" => spectral = true,
+            _ => return Err(Error::Parse(format!("unknown argument '{}'", arg))),
         }
-    } else {
-        let unit_direction: Vec3 = r.direction.unit_vector();
-        let t: f32 = 0.5 * (unit_direction.y + 1.0);
-        (1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0)
     }
+    Ok((crop, output, spectral))
 }
 
 fn main() {
-    let nx: u32 = 400;
-    let ny: u32 = 300;
-    let ns: u32 = 10; // number of samples inside each pixel
+    if let Err(e) = run() {
+        eprintln!("error: {}", e);
+        if let Error::Parse(_) = e {
+            eprintln!("{}", USAGE);
+        }
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
+    let mut args = env::args().skip(1).peekable();
+    match args.peek().map(String::as_str) {
+        Some("bench") => run_bench(args.skip(1)),
+        Some("imgdiff") => run_imgdiff(args.skip(1)),
+        _ => render(args),
+    }
+}
+
+fn render<I: Iterator<Item = String>>(args: I) -> Result<()> {
+    let mut settings = RenderSettings {
+        width: 400,
+        height: 300,
+        samples: 10,
+        ..Default::default()
+    };
+    let nx = settings.width;
+    let ny = settings.height;
+
+    let (crop, output, spectral) = parse_args(args, nx, ny)?;
+    settings.crop = Some(crop);
+    settings.spectral = spectral;
+    settings.validate()?;
+    let crop = settings.window();
 
     // Objects setup
-    let world = scene::room_scene();
+    let start = Instant::now();
+    let objects = scene::random_scene().hitables;
+    let scene_build = start.elapsed();
 
     // Camera setup
-    let lookfrom: Vec3 = Vec3::new(50.0, 52.0, 300.0);
-    let lookat: Vec3 = lookfrom + Vec3::new(0.0, -0.043, -1.0);
-    let vup: Vec3 = Vec3::new(0.0, 1.0, 0.0);
-    let vfov: f32 = 30.0;
-    let aspect: f32 = nx as f32 / ny as f32;
-    let aperture: f32 = 0.0;
-    let dist_to_focus: f32 = (lookfrom - lookat).length();
-    let cam = Camera::new(lookfrom, lookat, vup, vfov, aspect, aperture, dist_to_focus);
+    let cam = scene::random_scene_camera(settings.aspect());
+
+    let start = Instant::now();
+    let world = Bvh::new(objects, cam.time0, cam.time1);
+    let accel_build = start.elapsed();
 
     // Parallell process
+    let progress = ProgressBar::new(crop.width() as u64 * crop.height() as u64);
+    let rendered = Renderer::new(settings)
+        .on_progress(|p| progress.update(p.pixels_done, p.rays))
+        .render(&world, &cam)?;
+    progress.finish();
+
+    let mut stats = rendered.stats;
+    stats.scene_build = scene_build;
+    stats.accel_build = accel_build;
+
     let start = Instant::now();
+    let image = match output {
+        CropOutput::Cropped => rendered.image,
+        CropOutput::FullFrame => {
+            let mut frame = Image::new(nx, ny);
+            frame.paste(&rendered.image, crop.x0, crop.y0);
+            frame
+        }
+    };
+    image.save_ppm("result.ppm")?;
+    stats.write = start.elapsed();
 
-    let mut f = BufWriter::new(fs::File::create("result.ppm").unwrap());
-    f.write_all(format!("P3\n{} {}\n255\n", nx, ny).as_bytes())
-        .unwrap();
+    println!("{}", stats);
+    Ok(())
+}
 
-    let par_vec: Vec<(u32, u32)> = {
-        let mut v: Vec<(u32, u32)> = vec![];
+fn run_bench<I: Iterator<Item = String>>(mut args: I) -> Result<()> {
+    let mut options = BenchOptions::default();
+    let mut out = String::from("bench.json");
 
-        for j in (0..ny).rev() {
-            for i in 0..nx {
-                v.push((i, j));
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--quick" => options = BenchOptions::quick(),
+            "--out" => {
+                out = args
+                    .next()
+                    .ok_or_else(|| Error::Parse("--out needs a value".to_string()))?
             }
+            _ => return Err(Error::Parse(format!("unknown argument '{}'", arg))),
         }
-        v
-    };
+    }
 
-    let pixels: Vec<Vec3> = par_vec
-        .par_iter()
-        .cloned()
-        .map(|(i, j)| {
-            let mut col: Vec3 = (0..ns)
-                .into_par_iter()
-                .map(|_| {
-                    let u = (i as f32 + rand::random::<f32>()) / nx as f32;
-                    let v = (j as f32 + rand::random::<f32>()) / ny as f32;
-                    let r: Ray = cam.get_ray(u, v);
-                    color(&r, &world, 0)
-                })
-                .reduce(|| Vec3::new(0.0, 0.0, 0.0), |sum, x| sum + x);
-            col /= ns as f32;
-            col = Vec3::new(col.x.sqrt(), col.y.sqrt(), col.z.sqrt());
-            col
-        })
-        .collect();
-
-    for pix in pixels {
-        let ir = (255.99 * pix.x) as i32;
-        let ig = (255.99 * pix.y) as i32;
-        let ib = (255.99 * pix.z) as i32;
-        f.write(format!("{} {} {}\n", ir, ig, ib).as_bytes())
-            .unwrap();
+    println!(
+        "{:<32} {:>12} {:>16} {:>10}",
+        "benchmark", "time/iter", "throughput", "iters"
+    );
+    let results = bench::run(&options, |r| {
+        println!(
+            "{:<32} {:>12.3?} {:>8.3} M{:<7} {:>10}",
+            r.name,
+            r.time_per_iter(),
+            r.items_per_second() / 1.0e6,
+            format!("{}/s", r.unit),
+            r.iterations
+        );
+    })?;
+
+    // Tag the results with the current commit when run from a git checkout
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string());
+    fs::write(&out, bench::to_json(&results, commit.as_deref())).map_err(Error::at(&out))?;
+    println!("results written to {}", out);
+    Ok(())
+}
+
+fn run_imgdiff<I: Iterator<Item = String>>(mut args: I) -> Result<()> {
+    let mut paths: Vec<String> = vec![];
+    let mut diff = String::from("diff.ppm");
+    let mut scale: Option<f32> = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--diff" => {
+                diff = args
+                    .next()
+                    .ok_or_else(|| Error::Parse("--diff needs a value".to_string()))?
+            }
+            "--scale" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| Error::Parse("--scale needs a value".to_string()))?;
+                scale = match value.parse::<f32>() {
+                    Ok(s) if s > 0.0 => Some(s),
+                    _ => {
+                        return Err(Error::Parse(format!(
+                            "--scale expects a positive number, got '{}'",
+                            value
+                        )))
+                    }
+                };
+            }
+            _ if arg.starts_with("--") => {
+                return Err(Error::Parse(format!("unknown argument '{}'", arg)))
+            }
+            _ => paths.push(arg),
+        }
     }
+    if paths.len() != 2 {
+        return Err(Error::Parse(
+            "imgdiff expects an image and a reference".to_string(),
+        ));
+    }
+
+    let image = Image::load(&paths[0])?;
+    let reference = Image::load(&paths[1])?;
+    println!("{}", metrics::compare(&image, &reference)?);
 
-    let duration = start.elapsed();
-    println!("Time elapsed in parallel process is: {:?}", duration);
+    let errors = metrics::error_map(&image, &reference)?;
+    let scale = scale.unwrap_or_else(|| errors.iter().cloned().fold(0.0, f32::max));
+    metrics::false_color(
+        image.width,
+        image.height,
+        &errors,
+        scale.max(f32::MIN_POSITIVE),
+    )
+    .save_ppm(&diff)?;
+    println!("difference written to {} (white: {:.4})", diff, scale);
+    Ok(())
 }
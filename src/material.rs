@@ -1,15 +1,132 @@
 use crate::hitable::HitRecord;
+use crate::microfacet::{self, TrowbridgeReitz};
+use crate::random;
 use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::thin_film::{Substrate, ThinFilm};
 use crate::vec3::Vec3;
-use rand;
+use std::f32::consts::PI;
+use std::sync::Arc;
 
 pub struct ScatterRecord {
     pub attenuation: Vec3,
     pub scattered: Ray,
 }
 
+// Index of refraction, constant or varying with the wavelength
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ior {
+    Constant(f32),
+    // n = a + b / λ², with λ in micrometers
+    Cauchy { a: f32, b: f32 },
+    // n² = 1 + Σ b[i] λ² / (λ² - c[i]), with λ in micrometers
+    Sellmeier { b: [f32; 3], c: [f32; 3] },
+}
+
+impl Ior {
+    // Borosilicate crown glass
+    pub const BK7: Ior = Ior::Sellmeier {
+        b: [1.039_612, 0.231_792_34, 1.010_469_5],
+        c: [0.006_000_699, 0.020_017_914, 103.560_65],
+    };
+    // Dense flint glass, strongly dispersive
+    pub const SF11: Ior = Ior::Sellmeier {
+        b: [1.737_597, 0.313_747_35, 1.898_781],
+        c: [0.013_188_707, 0.062_306_814, 155.236_3],
+    };
+
+    // Wavelength of the sodium d line, where indices are usually given
+    pub const D_LINE: f32 = 587.56;
+
+    // Index at `wavelength` in nanometers, or at the d line without one
+    pub fn at(&self, wavelength: Option<f32>) -> f32 {
+        let l = wavelength.unwrap_or(Ior::D_LINE) / 1000.0;
+        let l2 = l * l;
+        match *self {
+            Ior::Constant(n) => n,
+            Ior::Cauchy { a, b } => a + b / l2,
+            Ior::Sellmeier { b, c } => {
+                let sum: f32 = (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant(_))
+    }
+}
+
+// Matter filling the inside of a closed surface, such as the glass of a
+// marble or the water in a glass
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interior {
+    pub ior: Ior,
+    // absorption coefficient, per unit length
+    pub absorption: Vec3,
+    // scattering coefficient, per unit length, and mean cosine of the
+    // deflection of the Henyey-Greenstein phase function
+    pub scattering: Vec3,
+    pub anisotropy: f32,
+    // Where media overlap, the one of highest priority fills the overlap and
+    // the surfaces of the others are ignored there.
+    pub priority: u32,
+}
+
 pub trait Material: Send + Sync {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord>;
+
+    // Medium inside the surface. The renderer keeps track of the media a
+    // path is in to attenuate it and to pass their indices to
+    // `scatter_between`.
+    fn interior(&self) -> Option<Interior> {
+        None
+    }
+
+    // Whether `scatter` sends different wavelengths different ways, which
+    // ends the secondary wavelengths of a spectral path
+    fn is_dispersive(&self) -> bool {
+        false
+    }
+
+    // `scatter` at the boundary between the medium of index `n_from`, where
+    // the ray comes from, and the medium of index `n_to`
+    fn scatter_between(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        _n_from: f32,
+        _n_to: f32,
+    ) -> Option<ScatterRecord> {
+        self.scatter(r_in, rec)
+    }
+
+    // BRDF times the cosine of `wi` with the normal, for light arriving from
+    // `wi` and leaving towards `wo`. Both are unit vectors pointing away from
+    // the surface. For volumes, the phase function times the albedo.
+    // Materials whose `scatter` only follows perfectly specular directions
+    // (mirror, smooth glass) cannot be evaluated and return zero.
+    fn eval(&self, _rec: &HitRecord, _wo: &Vec3, _wi: &Vec3) -> Vec3 {
+        Vec3::new(0.0, 0.0, 0.0)
+    }
+
+    // Probability density, per unit solid angle, that `scatter` sends a ray
+    // arriving from `wo` towards `wi`. Zero when `eval` is not available.
+    fn pdf(&self, _rec: &HitRecord, _wo: &Vec3, _wi: &Vec3) -> f32 {
+        0.0
+    }
+
+    // Radiance given off by the surface at `rec`, on both sides unless the
+    // material is `Sided`
+    fn emitted(&self, _rec: &HitRecord) -> Vec3 {
+        Vec3::new(0.0, 0.0, 0.0)
+    }
+
+    // Whether the surface is there at `rec`. Objects skip the hits where it
+    // is not, as if the ray went through a hole.
+    fn alpha_test(&self, _rec: &HitRecord) -> bool {
+        true
+    }
 }
 
 pub struct Lambertian {
@@ -25,12 +142,14 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
-        let target: Vec3 = rec.p + rec.normal + random_in_unit_sphere();
-        let scattered = Ray {
-            origin: rec.p,
-            direction: target - rec.p,
-        };
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        // A point on the unit sphere tangent to the surface gives directions
+        // distributed with the cosine, so that the weight is the albedo.
+        let mut direction: Vec3 = rec.normal + random_unit_vector();
+        if direction.squared_length() < 1e-12 {
+            direction = rec.normal;
+        }
+        let scattered = Ray::with_time(rec.p, direction, r_in.time);
         let attenuation: Vec3 = self.albedo;
 
         Some(ScatterRecord {
@@ -38,11 +157,25 @@ impl Material for Lambertian {
             scattered,
         })
     }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        if rec.normal.dot(wo) <= 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let cosine = rec.normal.dot(wi).max(0.0);
+        self.albedo * (cosine / PI)
+    }
+
+    fn pdf(&self, rec: &HitRecord, _wo: &Vec3, wi: &Vec3) -> f32 {
+        rec.normal.dot(wi).max(0.0) / PI
+    }
 }
 
 pub struct Metal {
     pub albedo: Vec3,
     pub fuzz: f32,
+    // oxide or oil on the surface
+    pub film: Option<ThinFilm>,
 }
 
 impl Metal {
@@ -51,19 +184,35 @@ impl Metal {
         Metal {
             albedo: Vec3::new(v.0, v.1, v.2),
             fuzz,
+            film: None,
         }
     }
+
+    // Anodized or oily metal
+    pub fn with_film(mut self, film: ThinFilm) -> Self {
+        self.film = Some(film);
+        self
+    }
 }
 
 impl Material for Metal {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
         let f: f32 = if self.fuzz < 1.0 { self.fuzz } else { 1.0 };
         let reflected: Vec3 = reflect(r_in.direction.unit_vector(), rec.normal);
-        let scattered = Ray {
-            origin: rec.p,
-            direction: reflected + f * random_in_unit_sphere(),
+        let scattered = Ray::with_time(rec.p, reflected + f * random_in_unit_sphere(), r_in.time);
+        let attenuation: Vec3 = match &self.film {
+            Some(film) => {
+                let cosine = r_in.direction.unit_vector().dot(&rec.normal).abs();
+                film.reflectance(
+                    rec,
+                    cosine,
+                    1.0,
+                    Substrate::Mirror(self.albedo),
+                    r_in.wavelength,
+                )
+            }
+            None => self.albedo,
         };
-        let attenuation: Vec3 = self.albedo;
 
         if scattered.direction.dot(&rec.normal) > 0.0 {
             Some(ScatterRecord {
@@ -74,73 +223,545 @@ impl Material for Metal {
             None
         }
     }
+
+    // the film reflects each wavelength its own way
+    fn is_dispersive(&self) -> bool {
+        self.film.is_some()
+    }
+}
+
+// Phase function of volumes scattering in all directions alike
+pub struct Isotropic {
+    pub albedo: Vec3,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Vec3) -> Self {
+        Isotropic { albedo }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord {
+            attenuation: self.albedo,
+            scattered: Ray::with_time(rec.p, random_unit_vector(), r_in.time),
+        })
+    }
+
+    fn eval(&self, _rec: &HitRecord, _wo: &Vec3, _wi: &Vec3) -> Vec3 {
+        self.albedo / (4.0 * PI)
+    }
+
+    fn pdf(&self, _rec: &HitRecord, _wo: &Vec3, _wi: &Vec3) -> f32 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+// Phase function of volumes scattering mostly forwards (`g` > 0) or
+// backwards (`g` < 0), with `g` the mean cosine of the deflection
+pub struct HenyeyGreenstein {
+    pub albedo: Vec3,
+    pub g: f32,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(albedo: Vec3, g: f32) -> Self {
+        HenyeyGreenstein {
+            albedo,
+            g: g.clamp(-0.99, 0.99),
+        }
+    }
+
+    // Density for the cosine of the angle between the directions of
+    // propagation before and after scattering
+    fn phase(&self, cos_theta: f32) -> f32 {
+        let g = self.g;
+        let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denom * denom.sqrt())
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let g = self.g;
+        let xi = random::next_f32();
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * xi
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * random::next_f32();
+
+        let (forward, t, b) = orthonormal_basis(&r_in.direction.unit_vector());
+        let direction = cos_theta * forward + sin_theta * (phi.cos() * t + phi.sin() * b);
+        Some(ScatterRecord {
+            attenuation: self.albedo,
+            scattered: Ray::with_time(rec.p, direction, r_in.time),
+        })
+    }
+
+    fn eval(&self, _rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        // the ray travels along -wo and continues along wi
+        self.albedo * self.phase(-wo.dot(wi))
+    }
+
+    fn pdf(&self, _rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f32 {
+        self.phase(-wo.dot(wi))
+    }
+}
+
+// `n` and two unit vectors completing it into an orthonormal basis
+pub(crate) fn orthonormal_basis(n: &Vec3) -> (Vec3, Vec3, Vec3) {
+    let a = if n.x.abs() > 0.9 {
+        Vec3::new(0.0, 1.0, 0.0)
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let t = n.cross(&a).unit_vector();
+    let b = n.cross(&t);
+    (*n, t, b)
+}
+
+// Local frame of a surface, where the normal is +z
+pub(crate) struct Frame {
+    t: Vec3,
+    b: Vec3,
+    n: Vec3,
+}
+
+impl Frame {
+    pub(crate) fn new(normal: &Vec3) -> Self {
+        let (n, t, b) = orthonormal_basis(normal);
+        Frame { t, b, n }
+    }
+
+    pub(crate) fn to_local(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(v.dot(&self.t), v.dot(&self.b), v.dot(&self.n))
+    }
+
+    pub(crate) fn to_world(&self, v: &Vec3) -> Vec3 {
+        v.x * self.t + v.y * self.b + v.z * self.n
+    }
+}
+
+// Metal with the measured optical constants of a real conductor, rough or
+// polished. Light is reflected by microfacets with the GGX distribution.
+pub struct Conductor {
+    // complex index of refraction eta + i k, per color channel
+    pub eta: Vec3,
+    pub k: Vec3,
+    pub distribution: TrowbridgeReitz,
+}
+
+impl Conductor {
+    // `roughness` in [0, 1]
+    pub fn new(eta: Vec3, k: Vec3, roughness: f32) -> Self {
+        Conductor {
+            eta,
+            k,
+            distribution: TrowbridgeReitz::from_roughness(roughness),
+        }
+    }
+
+    pub fn gold(roughness: f32) -> Self {
+        Conductor::new(
+            Vec3::new(0.143_119, 0.374_957, 1.442_48),
+            Vec3::new(3.983_16, 2.385_72, 1.603_22),
+            roughness,
+        )
+    }
+
+    pub fn copper(roughness: f32) -> Self {
+        Conductor::new(
+            Vec3::new(0.200_438, 0.924_033, 1.102_21),
+            Vec3::new(3.912_95, 2.452_85, 2.142_19),
+            roughness,
+        )
+    }
+
+    pub fn aluminum(roughness: f32) -> Self {
+        Conductor::new(
+            Vec3::new(1.657_46, 0.880_369, 0.521_229),
+            Vec3::new(9.223_87, 6.269_52, 4.837),
+            roughness,
+        )
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let frame = Frame::new(&rec.normal);
+        let wo = frame.to_local(&-r_in.direction.unit_vector());
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let wm = self
+            .distribution
+            .sample_visible(&wo, random::next_f32(), random::next_f32());
+        let wi = reflect(-wo, wm);
+        if wi.z <= 0.0 {
+            return None;
+        }
+        // eval / pdf: D and the cosines cancel out
+        let fresnel = microfacet::fresnel_conductor(wo.dot(&wm), &self.eta, &self.k);
+        let masking = self.distribution.g(&wo, &wi) / self.distribution.g1(&wo);
+        Some(ScatterRecord {
+            attenuation: fresnel * masking,
+            scattered: Ray::with_time(rec.p, frame.to_world(&wi), r_in.time),
+        })
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        let frame = Frame::new(&rec.normal);
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let wm = (wo + wi).unit_vector();
+        let fresnel = microfacet::fresnel_conductor(wo.dot(&wm), &self.eta, &self.k);
+        let d = self.distribution.d(&wm);
+        let g = self.distribution.g(&wo, &wi);
+        fresnel * (d * g / (4.0 * wo.z))
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f32 {
+        let frame = Frame::new(&rec.normal);
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let wm = (wo + wi).unit_vector();
+        self.distribution.d_visible(&wo, &wm) / (4.0 * wo.dot(&wm))
+    }
+}
+
+// Frosted glass: light is reflected or refracted by microfacets with the
+// GGX distribution. Like `Dielectric`, the radiance is not scaled by the
+// squared ratio of the indices, which cancels out through closed objects.
+pub struct RoughDielectric {
+    pub ior: Ior,
+    pub distribution: TrowbridgeReitz,
+    // absorption coefficient of the inside, per unit length
+    pub absorption: Vec3,
+    pub priority: u32,
+}
+
+impl RoughDielectric {
+    // `roughness` in [0, 1]
+    pub fn new(refract_idx: f32, roughness: f32) -> Self {
+        RoughDielectric::with_ior(Ior::Constant(refract_idx), roughness)
+    }
+
+    pub fn with_ior(ior: Ior, roughness: f32) -> Self {
+        RoughDielectric {
+            ior,
+            distribution: TrowbridgeReitz::from_roughness(roughness),
+            absorption: Vec3::new(0.0, 0.0, 0.0),
+            priority: 0,
+        }
+    }
+
+    // Frosted colored glass, which lets through the fraction `color` of the
+    // light crossing `distance` inside it
+    pub fn tinted(refract_idx: f32, roughness: f32, color: Vec3, distance: f32) -> Self {
+        let absorption = |c: f32| -c.clamp(1e-6, 1.0).ln() / distance;
+        RoughDielectric {
+            absorption: Vec3::new(
+                absorption(color.x),
+                absorption(color.y),
+                absorption(color.z),
+            ),
+            ..RoughDielectric::new(refract_idx, roughness)
+        }
+    }
+
+    pub fn with_priority(mut self, priority: u32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    // Frame with the normal on the side of `wo`, and the index of the other
+    // side relative to the side of `wo`
+    fn local(&self, rec: &HitRecord, wo: &Vec3) -> (Frame, f32) {
+        let n = self.ior.at(None);
+        let outward = rec.outward_normal();
+        if wo.dot(&outward) >= 0.0 {
+            (Frame::new(&outward), n)
+        } else {
+            (Frame::new(&-outward), 1.0 / n)
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    // Between this material and the void
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let refract_idx = self.ior.at(r_in.wavelength);
+        if rec.front_face {
+            self.scatter_between(r_in, rec, 1.0, refract_idx)
+        } else {
+            self.scatter_between(r_in, rec, refract_idx, 1.0)
+        }
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.ior.is_dispersive()
+    }
+
+    fn interior(&self) -> Option<Interior> {
+        Some(Interior {
+            ior: self.ior,
+            absorption: self.absorption,
+            scattering: Vec3::new(0.0, 0.0, 0.0),
+            anisotropy: 0.0,
+            priority: self.priority,
+        })
+    }
+
+    fn scatter_between(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        n_from: f32,
+        n_to: f32,
+    ) -> Option<ScatterRecord> {
+        let normal = if r_in.direction.dot(&rec.normal) > 0.0 {
+            -rec.normal
+        } else {
+            rec.normal
+        };
+        let frame = Frame::new(&normal);
+        let wo = frame.to_local(&-r_in.direction.unit_vector());
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let eta = n_to / n_from;
+        let wm = self
+            .distribution
+            .sample_visible(&wo, random::next_f32(), random::next_f32());
+        let cos_o = wo.dot(&wm);
+        let reflectance = microfacet::fresnel_dielectric(cos_o, eta);
+
+        let wi = if random::next_f32() < reflectance {
+            let wi = reflect(-wo, wm);
+            if wi.z <= 0.0 {
+                return None;
+            }
+            wi
+        } else {
+            match microfacet::refract(&wo, &wm, eta) {
+                Some(wi) if wi.z < 0.0 => wi,
+                _ => return None,
+            }
+        };
+        // eval / pdf: the Fresnel terms are those of the choice of the lobe
+        let masking = self.distribution.g(&wo, &wi) / self.distribution.g1(&wo);
+        Some(ScatterRecord {
+            attenuation: Vec3::new(masking, masking, masking),
+            scattered: Ray::with_time(rec.p, frame.to_world(&wi), r_in.time),
+        })
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        let (frame, eta) = self.local(rec, wo);
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+        let wm = match microfacet::half_vector(&wo, &wi, eta) {
+            Some(wm) => wm,
+            None => return Vec3::new(0.0, 0.0, 0.0),
+        };
+        let reflectance = microfacet::fresnel_dielectric(wo.dot(&wm), eta);
+        let d = self.distribution.d(&wm);
+        let g = self.distribution.g(&wo, &wi);
+        let value = if wi.z > 0.0 {
+            reflectance * d * g / (4.0 * wo.z)
+        } else {
+            let denom = wi.dot(&wm) + wo.dot(&wm) / eta;
+            (1.0 - reflectance) * d * g * (wi.dot(&wm) * wo.dot(&wm) / (wo.z * denom * denom)).abs()
+        };
+        Vec3::new(value, value, value)
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f32 {
+        let (frame, eta) = self.local(rec, wo);
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+        let wm = match microfacet::half_vector(&wo, &wi, eta) {
+            Some(wm) => wm,
+            None => return 0.0,
+        };
+        let reflectance = microfacet::fresnel_dielectric(wo.dot(&wm), eta);
+        let visible = self.distribution.d_visible(&wo, &wm);
+        if wi.z > 0.0 {
+            reflectance * visible / (4.0 * wo.dot(&wm))
+        } else {
+            let denom = wi.dot(&wm) + wo.dot(&wm) / eta;
+            (1.0 - reflectance) * visible * wi.dot(&wm).abs() / (denom * denom)
+        }
+    }
 }
 
 fn random_in_unit_sphere() -> Vec3 {
     let mut p = Vec3::make_unit_vector();
     while p.squared_length() > 1.0 {
-        p =
-            2.0 * Vec3::new(
-                rand::random::<f32>(),
-                rand::random::<f32>(),
-                rand::random::<f32>(),
-            ) - Vec3::new(1.0, 1.0, 1.0);
+        p = 2.0 * Vec3::new(random::next_f32(), random::next_f32(), random::next_f32())
+            - Vec3::new(1.0, 1.0, 1.0);
     }
     p
 }
 
-fn reflect(v: Vec3, n: Vec3) -> Vec3 {
+// Uniformly distributed on the unit sphere
+pub(crate) fn random_unit_vector() -> Vec3 {
+    loop {
+        let p = random_in_unit_sphere();
+        let len2 = p.squared_length();
+        if len2 > 1e-12 {
+            return p / len2.sqrt();
+        }
+    }
+}
+
+pub(crate) fn reflect(v: Vec3, n: Vec3) -> Vec3 {
     v - 2.0 * v.dot(&n) * n
 }
 
 pub struct Dielectric {
-    pub refract_idx: f32,
+    pub ior: Ior,
+    // absorption coefficient of the inside, per unit length
+    pub absorption: Vec3,
+    pub priority: u32,
+    // coating of the surface, e.g. the soap of a bubble
+    pub film: Option<ThinFilm>,
 }
 
 impl Dielectric {
     pub fn new(refract_idx: f32) -> Self {
-        Dielectric { refract_idx }
+        Dielectric::with_ior(Ior::Constant(refract_idx))
+    }
+
+    // Glass whose index varies with the wavelength, e.g. `Ior::BK7`. In RGB
+    // rendering it has the index of the d line.
+    pub fn with_ior(ior: Ior) -> Self {
+        Dielectric {
+            ior,
+            absorption: Vec3::new(0.0, 0.0, 0.0),
+            priority: 0,
+            film: None,
+        }
+    }
+
+    // Colored glass or liquid, which lets through the fraction `color` of the
+    // light crossing `distance` inside it
+    pub fn tinted(refract_idx: f32, color: Vec3, distance: f32) -> Self {
+        let absorption = |c: f32| -c.clamp(1e-6, 1.0).ln() / distance;
+        Dielectric {
+            absorption: Vec3::new(
+                absorption(color.x),
+                absorption(color.y),
+                absorption(color.z),
+            ),
+            ..Dielectric::new(refract_idx)
+        }
+    }
+
+    pub fn with_priority(mut self, priority: u32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    // Soap bubble with `Dielectric::new(1.0)`, coated lens otherwise
+    pub fn with_film(mut self, film: ThinFilm) -> Self {
+        self.film = Some(film);
+        self
     }
 }
 
 impl Material for Dielectric {
+    // Between this material and the void
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let refract_idx = self.ior.at(r_in.wavelength);
+        if rec.front_face {
+            self.scatter_between(r_in, rec, 1.0, refract_idx)
+        } else {
+            self.scatter_between(r_in, rec, refract_idx, 1.0)
+        }
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.ior.is_dispersive() || self.film.is_some()
+    }
+
+    fn interior(&self) -> Option<Interior> {
+        Some(Interior {
+            ior: self.ior,
+            absorption: self.absorption,
+            scattering: Vec3::new(0.0, 0.0, 0.0),
+            anisotropy: 0.0,
+            priority: self.priority,
+        })
+    }
+
+    fn scatter_between(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        n_from: f32,
+        n_to: f32,
+    ) -> Option<ScatterRecord> {
         let reflected: Vec3 = reflect(r_in.direction, rec.normal);
         let attenuation = Vec3::new(1.0, 1.0, 1.0);
 
-        let outward_normal: Vec3;
-        let ni_over_nt: f32;
-        let cosine: f32;
-        let reflect_prob: f32;
-
-        if r_in.direction.dot(&rec.normal) > 0.0 {
-            outward_normal = -rec.normal;
-            ni_over_nt = self.refract_idx;
-            cosine = self.refract_idx * r_in.direction.dot(&rec.normal) / r_in.direction.length();
+        let ni_over_nt = n_from / n_to;
+        let d_dot_n = r_in.direction.dot(&rec.normal);
+        let outward_normal = if d_dot_n > 0.0 {
+            -rec.normal
         } else {
-            outward_normal = rec.normal;
-            ni_over_nt = 1.0 / self.refract_idx;
-            cosine = -r_in.direction.dot(&rec.normal) / r_in.direction.length();
-        }
+            rec.normal
+        };
+        // Schlick's approximation takes the angle on the side of lower index
+        let cosine = if ni_over_nt > 1.0 {
+            ni_over_nt * d_dot_n.abs() / r_in.direction.length()
+        } else {
+            d_dot_n.abs() / r_in.direction.length()
+        };
 
         match refract(&r_in.direction, &outward_normal, ni_over_nt) {
             Some(refracted) => {
-                reflect_prob = schlick(cosine, self.refract_idx);
-                if rand::random::<f32>() > reflect_prob {
+                if let Some(film) = &self.film {
+                    // each channel is weighted by its own reflectance
+                    let cos_i = d_dot_n.abs() / r_in.direction.length();
+                    let substrate = Substrate::Dielectric(n_to);
+                    let r = film.reflectance(rec, cos_i, n_from, substrate, r_in.wavelength);
+                    let reflect_prob = (r.x + r.y + r.z) / 3.0;
+                    return if random::next_f32() < reflect_prob {
+                        Some(ScatterRecord {
+                            attenuation: r / reflect_prob,
+                            scattered: Ray::with_time(rec.p, reflected, r_in.time),
+                        })
+                    } else {
+                        Some(ScatterRecord {
+                            attenuation: (Vec3::new(1.0, 1.0, 1.0) - r) / (1.0 - reflect_prob),
+                            scattered: Ray::with_time(rec.p, refracted, r_in.time),
+                        })
+                    };
+                }
+                let reflect_prob = schlick(cosine, n_from, n_to);
+                if random::next_f32() > reflect_prob {
                     Some(ScatterRecord {
                         attenuation,
-                        scattered: Ray::new(rec.p, refracted),
+                        scattered: Ray::with_time(rec.p, refracted, r_in.time),
                     })
                 } else {
                     Some(ScatterRecord {
                         attenuation,
-                        scattered: Ray::new(rec.p, reflected),
+                        scattered: Ray::with_time(rec.p, reflected, r_in.time),
                     })
                 }
             }
             None => Some(ScatterRecord {
                 attenuation,
-                scattered: Ray::new(rec.p, reflected),
+                scattered: Ray::with_time(rec.p, reflected, r_in.time),
             }),
         }
     }
@@ -148,7 +769,7 @@ impl Material for Dielectric {
 
 fn refract(v: &Vec3, n: &Vec3, ni_over_nt: f32) -> Option<Vec3> {
     let uv: Vec3 = v.unit_vector();
-    let dt: f32 = uv.dot(&n);
+    let dt: f32 = uv.dot(n);
     let discriminant: f32 = 1.0 - ni_over_nt * ni_over_nt * (1.0 - dt * dt);
     if discriminant > 0.0 {
         let refracted: Vec3 = ni_over_nt * (uv - *n * dt) - *n * discriminant.sqrt();
@@ -158,8 +779,394 @@ fn refract(v: &Vec3, n: &Vec3, ni_over_nt: f32) -> Option<Vec3> {
     }
 }
 
-fn schlick(cosine: f32, refract_idx: f32) -> f32 {
-    let mut r0: f32 = (1.0 - refract_idx) / (1.0 + refract_idx);
+fn schlick(cosine: f32, n1: f32, n2: f32) -> f32 {
+    let mut r0: f32 = (n1 - n2) / (n1 + n2);
     r0 = r0 * r0;
     r0 + (1.0 - r0) * ((1.0 - cosine).powi(5))
 }
+
+// Translucent matter such as skin, wax or marble: a smooth dielectric surface
+// around a medium that scatters the light through it. The renderer follows
+// the paths in a random walk from one scattering event to the next until they
+// leave, so the object must be closed.
+pub struct Subsurface {
+    // color of the object, the fraction of the light diffusely coming back out
+    // of a thick slab of it
+    pub albedo: Vec3,
+    // mean distance between scattering events, per channel
+    pub mean_free_path: Vec3,
+    // mean cosine of the deflection at each event
+    pub anisotropy: f32,
+    pub ior: f32,
+    pub priority: u32,
+}
+
+impl Subsurface {
+    pub fn new(albedo: Vec3, mean_free_path: Vec3, ior: f32) -> Self {
+        Subsurface {
+            albedo,
+            mean_free_path,
+            anisotropy: 0.0,
+            ior,
+            priority: 0,
+        }
+    }
+
+    pub fn with_anisotropy(mut self, anisotropy: f32) -> Self {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: u32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    fn surface(&self) -> Dielectric {
+        Dielectric::new(self.ior)
+    }
+}
+
+// Albedo of a single scattering event giving the multiple scattering `albedo`,
+// by the fit of Chiang et al., "Practical and Controllable Subsurface
+// Scattering for Production Path Tracing", 2016. White stays lossless.
+fn single_scattering_albedo(albedo: f32) -> f32 {
+    let a = albedo.clamp(0.0, 1.0);
+    if a >= 1.0 {
+        1.0
+    } else {
+        1.0 - (a * (-5.09406 + a * (2.61188 - a * 4.31805))).exp()
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        self.surface().scatter(r_in, rec)
+    }
+
+    fn interior(&self) -> Option<Interior> {
+        let extinction = |mfp: f32| 1.0 / mfp.max(1e-6);
+        let extinction = Vec3::new(
+            extinction(self.mean_free_path.x),
+            extinction(self.mean_free_path.y),
+            extinction(self.mean_free_path.z),
+        );
+        let albedo = Vec3::new(
+            single_scattering_albedo(self.albedo.x),
+            single_scattering_albedo(self.albedo.y),
+            single_scattering_albedo(self.albedo.z),
+        );
+        Some(Interior {
+            ior: Ior::Constant(self.ior),
+            absorption: (Vec3::new(1.0, 1.0, 1.0) - albedo) * extinction,
+            scattering: albedo * extinction,
+            anisotropy: self.anisotropy,
+            priority: self.priority,
+        })
+    }
+
+    fn scatter_between(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        n_from: f32,
+        n_to: f32,
+    ) -> Option<ScatterRecord> {
+        self.surface().scatter_between(r_in, rec, n_from, n_to)
+    }
+}
+
+// Blend of two materials, `b` taking the fraction `factor` of the light, a
+// number or a mask. Only the surfaces are blended: the media inside are not.
+pub struct Mix {
+    pub a: Arc<dyn Material>,
+    pub b: Arc<dyn Material>,
+    pub factor: Arc<dyn Texture>,
+}
+
+impl Mix {
+    pub fn new<T: Texture + 'static>(
+        a: Arc<dyn Material>,
+        b: Arc<dyn Material>,
+        factor: T,
+    ) -> Self {
+        Mix {
+            a,
+            b,
+            factor: Arc::new(factor),
+        }
+    }
+
+    // Fraction of the light taken by `b`. Where one of them is cut out, the
+    // other takes it all, so that a hole is never shaded like the surface it
+    // removes.
+    fn factor(&self, rec: &HitRecord) -> f32 {
+        match (self.a.alpha_test(rec), self.b.alpha_test(rec)) {
+            (true, false) => 0.0,
+            (false, true) => 1.0,
+            _ => self.blend(rec),
+        }
+    }
+
+    // `factor` at `rec`, whatever is cut out
+    fn blend(&self, rec: &HitRecord) -> f32 {
+        self.factor.value(rec.u, rec.v, &rec.p).x.clamp(0.0, 1.0)
+    }
+}
+
+impl Material for Mix {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let chosen = if random::next_f32() < self.factor(rec) {
+            &self.b
+        } else {
+            &self.a
+        };
+        let mut srec = chosen.scatter(r_in, rec)?;
+        // Directions that both materials may sample are weighted by the blend
+        // (one-sample MIS); the others keep the weight of the chosen one.
+        let wo = -r_in.direction.unit_vector();
+        let wi = srec.scattered.direction.unit_vector();
+        if chosen.pdf(rec, &wo, &wi) > 0.0 {
+            srec.attenuation = self.eval(rec, &wo, &wi) / self.pdf(rec, &wo, &wi);
+        }
+        Some(srec)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.a.is_dispersive() || self.b.is_dispersive()
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        let f = self.factor(rec);
+        (1.0 - f) * self.a.eval(rec, wo, wi) + f * self.b.eval(rec, wo, wi)
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f32 {
+        let f = self.factor(rec);
+        (1.0 - f) * self.a.pdf(rec, wo, wi) + f * self.b.pdf(rec, wo, wi)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Vec3 {
+        let f = self.factor(rec);
+        (1.0 - f) * self.a.emitted(rec) + f * self.b.emitted(rec)
+    }
+
+    // Where only one of them is cut out, the surface is there as often as
+    // the other is chosen.
+    fn alpha_test(&self, rec: &HitRecord) -> bool {
+        match (self.a.alpha_test(rec), self.b.alpha_test(rec)) {
+            (a, b) if a == b => a,
+            (_, b) => (random::next_f32() < self.blend(rec)) == b,
+        }
+    }
+}
+
+// Thin smooth dielectric layer of index `ior` over `base`, like varnish or
+// the clear coat of car paint. It reflects light by Fresnel's law and lets the
+// rest reach the base, and out again, without bending it. Like the other
+// materials it is coated on both sides; the media inside are not kept.
+pub struct Coated {
+    pub base: Arc<dyn Material>,
+    pub ior: f32,
+}
+
+impl Coated {
+    pub fn new(base: Arc<dyn Material>, ior: f32) -> Self {
+        Coated { base, ior }
+    }
+}
+
+impl Material for Coated {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let direction = r_in.direction.unit_vector();
+        let cos_o = -direction.dot(&rec.normal);
+        // the coat is chosen as often as it reflects, which leaves the base
+        // the light going through on the way out only
+        if random::next_f32() < microfacet::fresnel_dielectric(cos_o, self.ior) {
+            return Some(ScatterRecord {
+                attenuation: Vec3::new(1.0, 1.0, 1.0),
+                scattered: Ray::with_time(rec.p, reflect(direction, rec.normal), r_in.time),
+            });
+        }
+        let mut srec = self.base.scatter(r_in, rec)?;
+        let cos_i = srec
+            .scattered
+            .direction
+            .unit_vector()
+            .dot(&rec.normal)
+            .abs();
+        srec.attenuation =
+            srec.attenuation * (1.0 - microfacet::fresnel_dielectric(cos_i, self.ior));
+        Some(srec)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive()
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Vec3 {
+        self.base.emitted(rec)
+    }
+
+    fn alpha_test(&self, rec: &HitRecord) -> bool {
+        self.base.alpha_test(rec)
+    }
+}
+
+// How `Masked` turns its alpha into holes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlphaMode {
+    // The surface is there where the alpha reaches the threshold
+    Cutout(f32),
+    // The surface is there with the probability alpha, so that partly
+    // transparent edges average out over the samples
+    Stochastic,
+}
+
+// `base` cut out where `alpha` is low, e.g. leaves on foliage cards or decals
+pub struct Masked {
+    pub base: Arc<dyn Material>,
+    pub alpha: Arc<dyn Texture>,
+    pub mode: AlphaMode,
+}
+
+impl Masked {
+    pub fn new<T: Texture + 'static>(base: Arc<dyn Material>, alpha: T, mode: AlphaMode) -> Self {
+        Masked {
+            base,
+            alpha: Arc::new(alpha),
+            mode,
+        }
+    }
+}
+
+impl Material for Masked {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        self.base.scatter(r_in, rec)
+    }
+
+    fn interior(&self) -> Option<Interior> {
+        self.base.interior()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive()
+    }
+
+    fn scatter_between(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        n_from: f32,
+        n_to: f32,
+    ) -> Option<ScatterRecord> {
+        self.base.scatter_between(r_in, rec, n_from, n_to)
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        self.base.eval(rec, wo, wi)
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f32 {
+        self.base.pdf(rec, wo, wi)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Vec3 {
+        self.base.emitted(rec)
+    }
+
+    fn alpha_test(&self, rec: &HitRecord) -> bool {
+        let alpha = self.alpha.value(rec.u, rec.v, &rec.p).x;
+        let kept = match self.mode {
+            AlphaMode::Cutout(threshold) => alpha >= threshold,
+            AlphaMode::Stochastic => alpha >= 1.0 || random::next_f32() < alpha,
+        };
+        kept && self.base.alpha_test(rec)
+    }
+}
+
+// How a `Sided` material looks from the back of its surface, the side the
+// geometric normal points away from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackFace {
+    // Black: nothing is scattered nor emitted there, e.g. a lamp lighting one
+    // way only
+    Black,
+    // Culled: rays go through as if the surface was not there, e.g. the walls
+    // of a room seen from outside
+    Culled,
+}
+
+// `base` on the front of the surface only. Materials are otherwise two-sided,
+// alike from both sides; media need both sides, so `base` should have no
+// interior.
+pub struct Sided {
+    pub base: Arc<dyn Material>,
+    pub back: BackFace,
+}
+
+impl Sided {
+    pub fn new(base: Arc<dyn Material>, back: BackFace) -> Self {
+        Sided { base, back }
+    }
+}
+
+impl Material for Sided {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        if rec.front_face {
+            self.base.scatter(r_in, rec)
+        } else {
+            None
+        }
+    }
+
+    fn interior(&self) -> Option<Interior> {
+        self.base.interior()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive()
+    }
+
+    fn scatter_between(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        n_from: f32,
+        n_to: f32,
+    ) -> Option<ScatterRecord> {
+        if rec.front_face {
+            self.base.scatter_between(r_in, rec, n_from, n_to)
+        } else {
+            None
+        }
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        if rec.front_face {
+            self.base.eval(rec, wo, wi)
+        } else {
+            Vec3::new(0.0, 0.0, 0.0)
+        }
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f32 {
+        if rec.front_face {
+            self.base.pdf(rec, wo, wi)
+        } else {
+            0.0
+        }
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Vec3 {
+        if rec.front_face {
+            self.base.emitted(rec)
+        } else {
+            Vec3::new(0.0, 0.0, 0.0)
+        }
+    }
+
+    fn alpha_test(&self, rec: &HitRecord) -> bool {
+        (rec.front_face || self.back != BackFace::Culled) && self.base.alpha_test(rec)
+    }
+}
@@ -0,0 +1,296 @@
+use crate::material::{Interior, Material};
+use crate::random;
+use crate::vec3::Vec3;
+
+// What happens when a path meets the surface of a medium
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Boundary {
+    // The surface is inside a medium of higher priority and is ignored.
+    Skip,
+    // Light goes from the medium of index `n_from` to the one of index `n_to`.
+    Refract { n_from: f32, n_to: f32 },
+}
+
+// Where a path going `distance` to the next surface ends up in a medium
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flight {
+    // It reaches the surface, with the light scaled by `weight`.
+    Through {
+        weight: Vec3,
+    },
+    // It scatters after `distance`, with the light scaled by `weight` and
+    // deflected by the phase function of `anisotropy`.
+    Scattered {
+        distance: f32,
+        weight: Vec3,
+        anisotropy: f32,
+    },
+}
+
+// Media a path is inside of, in the order it entered them. Nested and
+// overlapping objects such as a glass of water are resolved with priorities:
+// inside several media, the path is in the one of highest priority, or the
+// last one entered of those.
+#[derive(Debug, Clone, Default)]
+pub struct MediumStack {
+    // the address of the material identifies the medium
+    entries: Vec<(usize, Interior)>,
+}
+
+fn id(material: &dyn Material) -> usize {
+    material as *const dyn Material as *const () as usize
+}
+
+// Entry of highest priority, the last one among equals
+fn innermost<'a, I: Iterator<Item = &'a (usize, Interior)>>(entries: I) -> Option<&'a Interior> {
+    entries
+        .map(|(_, interior)| interior)
+        .fold(None, |best: Option<&Interior>, interior| match best {
+            Some(b) if b.priority > interior.priority => best,
+            _ => Some(interior),
+        })
+}
+
+impl MediumStack {
+    pub fn new() -> Self {
+        MediumStack::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Medium the path is in, `None` in the void
+    pub fn current(&self) -> Option<&Interior> {
+        innermost(self.entries.iter())
+    }
+
+    // Index of the current medium at `wavelength`
+    pub fn refract_idx(&self, wavelength: Option<f32>) -> f32 {
+        self.current().map_or(1.0, |m| m.ior.at(wavelength))
+    }
+
+    // Fraction of the light left after `distance` in the current medium
+    pub fn transmittance(&self, distance: f32) -> Vec3 {
+        match self.current() {
+            Some(m) if m.absorption != Vec3::new(0.0, 0.0, 0.0) => {
+                let a = m.absorption * -distance;
+                Vec3::new(a.x.exp(), a.y.exp(), a.z.exp())
+            }
+            _ => Vec3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    // Free flight of a path going `distance` to the next surface in the
+    // current medium. In scattering media the distance to the next event is
+    // sampled in one channel drawn at random, weighted against all three
+    // channels (one-sample MIS); others only attenuate.
+    pub fn flight(&self, distance: f32) -> Flight {
+        let m = match self.current() {
+            Some(m) if m.scattering != Vec3::new(0.0, 0.0, 0.0) => m,
+            _ => {
+                return Flight::Through {
+                    weight: self.transmittance(distance),
+                }
+            }
+        };
+        let extinction = m.absorption + m.scattering;
+        let sigma = [extinction.x, extinction.y, extinction.z];
+        let channel = ((3.0 * random::next_f32()) as usize).min(2);
+        let t = -(1.0 - random::next_f32()).ln() / sigma[channel];
+        let mean = |v: Vec3| (v.x + v.y + v.z) / 3.0;
+
+        if t < distance {
+            let a = extinction * -t;
+            let transmittance = Vec3::new(a.x.exp(), a.y.exp(), a.z.exp());
+            // density of scattering at t in each channel
+            let pdf = mean(extinction * transmittance);
+            Flight::Scattered {
+                distance: t,
+                weight: m.scattering * transmittance / pdf,
+                anisotropy: m.anisotropy,
+            }
+        } else {
+            let a = extinction * -distance;
+            let transmittance = Vec3::new(a.x.exp(), a.y.exp(), a.z.exp());
+            Flight::Through {
+                weight: transmittance / mean(transmittance),
+            }
+        }
+    }
+
+    // How the surface of `material`, filled with `interior`, is met by a path
+    // of `wavelength` going into it or out of it
+    pub fn boundary(
+        &self,
+        material: &dyn Material,
+        interior: &Interior,
+        entering: bool,
+        wavelength: Option<f32>,
+    ) -> Boundary {
+        if entering {
+            match self.current() {
+                Some(current) if current.priority > interior.priority => Boundary::Skip,
+                current => Boundary::Refract {
+                    n_from: current.map_or(1.0, |m| m.ior.at(wavelength)),
+                    n_to: interior.ior.at(wavelength),
+                },
+            }
+        } else {
+            let id = id(material);
+            match innermost(self.entries.iter().filter(|(m, _)| *m != id)) {
+                Some(other) if other.priority > interior.priority => Boundary::Skip,
+                other => Boundary::Refract {
+                    n_from: interior.ior.at(wavelength),
+                    n_to: other.map_or(1.0, |m| m.ior.at(wavelength)),
+                },
+            }
+        }
+    }
+
+    // Update the media after the path went through the surface of `material`
+    pub fn cross(&mut self, material: &dyn Material, interior: Interior, entering: bool) {
+        let id = id(material);
+        if entering {
+            self.entries.push((id, interior));
+        } else if let Some(i) = self.entries.iter().rposition(|(m, _)| *m == id) {
+            self.entries.remove(i);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Dielectric, RoughDielectric};
+
+    #[test]
+    fn glass_of_water() {
+        let glass = Dielectric::new(1.5).with_priority(2);
+        let water = Dielectric::tinted(1.33, Vec3::new(0.5, 0.8, 0.9), 2.0).with_priority(1);
+        let (glass_in, water_in) = (glass.interior().unwrap(), water.interior().unwrap());
+        let mut media = MediumStack::new();
+        assert_eq!(media.refract_idx(None), 1.0);
+        assert_eq!(media.transmittance(5.0), Vec3::new(1.0, 1.0, 1.0));
+
+        // into the glass, then through the water overlapping it
+        let b = media.boundary(&glass, &glass_in, true, None);
+        assert_eq!(
+            b,
+            Boundary::Refract {
+                n_from: 1.0,
+                n_to: 1.5
+            }
+        );
+        media.cross(&glass, glass_in, true);
+        assert_eq!(
+            media.boundary(&water, &water_in, true, None),
+            Boundary::Skip
+        );
+        media.cross(&water, water_in, true);
+        assert_eq!(media.refract_idx(None), 1.5);
+        let b = media.boundary(&glass, &glass_in, false, None);
+        assert_eq!(
+            b,
+            Boundary::Refract {
+                n_from: 1.5,
+                n_to: 1.33
+            }
+        );
+        media.cross(&glass, glass_in, false);
+
+        // in the water
+        assert_eq!(media.refract_idx(None), 1.33);
+        let t = media.transmittance(2.0);
+        assert!((t - Vec3::new(0.5, 0.8, 0.9)).length() < 1e-5);
+        let b = media.boundary(&water, &water_in, false, None);
+        assert_eq!(
+            b,
+            Boundary::Refract {
+                n_from: 1.33,
+                n_to: 1.0
+            }
+        );
+        media.cross(&water, water_in, false);
+        assert!(media.is_empty());
+    }
+
+    #[test]
+    fn frosted_ice_in_water() {
+        let water = Dielectric::new(1.33).with_priority(1);
+        let ice =
+            RoughDielectric::tinted(1.31, 0.3, Vec3::new(0.6, 0.9, 1.0), 0.5).with_priority(2);
+        let (water_in, ice_in) = (water.interior().unwrap(), ice.interior().unwrap());
+        let mut media = MediumStack::new();
+        media.cross(&water, water_in, true);
+
+        let b = media.boundary(&ice, &ice_in, true, None);
+        assert_eq!(
+            b,
+            Boundary::Refract {
+                n_from: 1.33,
+                n_to: 1.31
+            }
+        );
+        media.cross(&ice, ice_in, true);
+        let t = media.transmittance(0.5);
+        assert!((t - Vec3::new(0.6, 0.9, 1.0)).length() < 1e-5);
+    }
+
+    #[test]
+    fn scattering_flights() {
+        let water = Dielectric::tinted(1.33, Vec3::new(0.5, 0.8, 0.9), 2.0);
+        let mut media = MediumStack::new();
+        media.cross(&water, water.interior().unwrap(), true);
+        // only absorbs
+        assert_eq!(
+            media.flight(2.0),
+            Flight::Through {
+                weight: media.transmittance(2.0)
+            }
+        );
+
+        let absorption = Vec3::new(0.2, 0.5, 1.0);
+        let scattering = Vec3::new(1.0, 0.5, 0.2);
+        let fog = Interior {
+            scattering,
+            absorption,
+            anisotropy: 0.3,
+            ..water.interior().unwrap()
+        };
+        let mut media = MediumStack::new();
+        media.cross(&water, fog, true);
+
+        // on average, the light reaching the surface is attenuated by all of
+        // the extinction and the scattered light is what the medium scatters
+        // on the way
+        random::seed(3);
+        let n = 100_000;
+        let distance = 1.5;
+        let (mut through, mut scattered) = (Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+        for _ in 0..n {
+            match media.flight(distance) {
+                Flight::Through { weight } => through += weight,
+                Flight::Scattered {
+                    distance: t,
+                    weight,
+                    anisotropy,
+                } => {
+                    assert!(t < distance && anisotropy == 0.3);
+                    scattered += weight;
+                }
+            }
+        }
+        let extinction = absorption + scattering;
+        let a = extinction * -distance;
+        let transmittance = Vec3::new(a.x.exp(), a.y.exp(), a.z.exp());
+        let scattered_fraction = |s: f32, e: f32, t: f32| s * (1.0 - t) / e;
+        let expected = Vec3::new(
+            scattered_fraction(scattering.x, extinction.x, transmittance.x),
+            scattered_fraction(scattering.y, extinction.y, transmittance.y),
+            scattered_fraction(scattering.z, extinction.z, transmittance.z),
+        );
+        assert!((through / n as f32 - transmittance).length() < 0.01);
+        assert!((scattered / n as f32 - expected).length() < 0.01);
+    }
+}
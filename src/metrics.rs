@@ -0,0 +1,303 @@
+// Error metrics between two images of the same size, used to check
+// renders against references.
+use crate::error::{Error, Result};
+use crate::image::Image;
+use crate::vec3::Vec3;
+use std::fmt;
+
+fn check_same_size(a: &Image, b: &Image) -> Result<()> {
+    if (a.width, a.height) == (b.width, b.height) {
+        Ok(())
+    } else {
+        Err(Error::InvalidImage(format!(
+            "cannot compare a {}x{} image with a {}x{} image",
+            a.width, a.height, b.width, b.height
+        )))
+    }
+}
+
+// Mean squared error over all channels
+pub fn mse(a: &Image, b: &Image) -> Result<f32> {
+    check_same_size(a, b)?;
+    let n = 3 * a.pixels.len();
+    let sum: f64 = a
+        .pixels
+        .iter()
+        .zip(&b.pixels)
+        .map(|(p, q)| (*p - *q).squared_length() as f64)
+        .sum();
+    Ok(if n > 0 { (sum / n as f64) as f32 } else { 0.0 })
+}
+
+// Root mean squared error over all channels
+pub fn rmse(a: &Image, b: &Image) -> Result<f32> {
+    Ok(mse(a, b)?.sqrt())
+}
+
+// Peak signal to noise ratio in dB, for a peak value of 1. Infinite for
+// identical images.
+pub fn psnr(a: &Image, b: &Image) -> Result<f32> {
+    Ok(-10.0 * mse(a, b)?.log10())
+}
+
+// Offset of the denominator of the relative MSE, so that black reference
+// pixels do not dominate
+const RELATIVE_MSE_EPSILON: f32 = 0.01;
+
+// Relative MSE of `image` against `reference`: each squared error is divided
+// by the squared reference value, so that errors in dark and bright regions
+// count the same. The usual measure of Monte Carlo convergence.
+pub fn relative_mse(image: &Image, reference: &Image) -> Result<f32> {
+    check_same_size(image, reference)?;
+    let relative = |p: f32, q: f32| (p - q) * (p - q) / (q * q + RELATIVE_MSE_EPSILON);
+    let n = 3 * image.pixels.len();
+    let sum: f64 = image
+        .pixels
+        .iter()
+        .zip(&reference.pixels)
+        .map(|(p, q)| (relative(p.x, q.x) + relative(p.y, q.y) + relative(p.z, q.z)) as f64)
+        .sum();
+    Ok(if n > 0 { (sum / n as f64) as f32 } else { 0.0 })
+}
+
+// Per-pixel structural similarity (SSIM) of the luminances, clamped to
+// [0, 1] and gamma encoded as in the written images, with the usual Gaussian
+// window of sigma 1.5 pixels. 1 where the images are identical.
+pub fn ssim_map(a: &Image, b: &Image) -> Result<Vec<f32>> {
+    const C1: f32 = 0.01 * 0.01;
+    const C2: f32 = 0.03 * 0.03;
+    check_same_size(a, b)?;
+    let luma = |c: &Vec3| {
+        (0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z)
+            .clamp(0.0, 1.0)
+            .sqrt()
+    };
+
+    // The five local moments are filtered as the channels of two images
+    let mut moments1 = Image::new(a.width, a.height);
+    let mut moments2 = Image::new(a.width, a.height);
+    for (i, (p, q)) in a.pixels.iter().zip(&b.pixels).enumerate() {
+        let (x, y) = (luma(p), luma(q));
+        moments1.pixels[i] = Vec3::new(x, y, x * x);
+        moments2.pixels[i] = Vec3::new(y * y, x * y, 0.0);
+    }
+    let moments1 = gaussian_blur(&moments1, 1.5);
+    let moments2 = gaussian_blur(&moments2, 1.5);
+
+    Ok(moments1
+        .pixels
+        .iter()
+        .zip(&moments2.pixels)
+        .map(|(m1, m2)| {
+            let (mu_x, mu_y) = (m1.x, m1.y);
+            let var_x = m1.z - mu_x * mu_x;
+            let var_y = m2.x - mu_y * mu_y;
+            let cov = m2.y - mu_x * mu_y;
+            (2.0 * mu_x * mu_y + C1) * (2.0 * cov + C2)
+                / ((mu_x * mu_x + mu_y * mu_y + C1) * (var_x + var_y + C2))
+        })
+        .collect())
+}
+
+// Mean of `ssim_map`
+pub fn ssim(a: &Image, b: &Image) -> Result<f32> {
+    let map = ssim_map(a, b)?;
+    Ok(map.iter().sum::<f32>() / map.len().max(1) as f32)
+}
+
+// Per-pixel root mean squared difference over the channels
+pub fn error_map(a: &Image, b: &Image) -> Result<Vec<f32>> {
+    check_same_size(a, b)?;
+    Ok(a.pixels
+        .iter()
+        .zip(&b.pixels)
+        .map(|(p, q)| ((*p - *q).squared_length() / 3.0).sqrt())
+        .collect())
+}
+
+// The metrics of an image against a reference
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Comparison {
+    pub mse: f32,
+    pub psnr: f32,
+    pub ssim: f32,
+    pub relative_mse: f32,
+}
+
+pub fn compare(image: &Image, reference: &Image) -> Result<Comparison> {
+    Ok(Comparison {
+        mse: mse(image, reference)?,
+        psnr: psnr(image, reference)?,
+        ssim: ssim(image, reference)?,
+        relative_mse: relative_mse(image, reference)?,
+    })
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "MSE          {:.6e}", self.mse)?;
+        writeln!(f, "PSNR         {:.2} dB", self.psnr)?;
+        writeln!(f, "SSIM         {:.4}", self.ssim)?;
+        write!(f, "relative MSE {:.6e}", self.relative_mse)
+    }
+}
+
+// Per-pixel perceptual error in [0, 1], in the spirit of FLIP: both images
+// are low-pass filtered as seen by the eye (Gaussian of `sigma` pixels,
+// which also hides per-pixel sampling noise), then compared as CIELAB
+// color differences. An error of 1 is a difference of 100 in L*a*b*.
+pub fn perceptual_error_map(a: &Image, b: &Image, sigma: f32) -> Result<Vec<f32>> {
+    check_same_size(a, b)?;
+    let a = gaussian_blur(a, sigma);
+    let b = gaussian_blur(b, sigma);
+    Ok(a.pixels
+        .iter()
+        .zip(&b.pixels)
+        .map(|(p, q)| ((to_lab(*p) - to_lab(*q)).length() / 100.0).min(1.0))
+        .collect())
+}
+
+// Mean of `perceptual_error_map`
+pub fn perceptual_error(a: &Image, b: &Image, sigma: f32) -> Result<f32> {
+    let map = perceptual_error_map(a, b, sigma)?;
+    Ok(map.iter().sum::<f32>() / map.len().max(1) as f32)
+}
+
+// Visualize per-pixel errors in [0, 1] as a black-red-yellow-white ramp,
+// with errors of `scale` and above shown white.
+pub fn false_color(width: u32, height: u32, errors: &[f32], scale: f32) -> Image {
+    let ramp = |e: f32| -> Vec3 {
+        let t = (3.0 * e / scale).clamp(0.0, 3.0);
+        Vec3::new(
+            t.min(1.0),
+            (t - 1.0).clamp(0.0, 1.0),
+            (t - 2.0).clamp(0.0, 1.0),
+        )
+    };
+    Image {
+        width,
+        height,
+        pixels: errors.iter().map(|&e| ramp(e)).collect(),
+    }
+}
+
+// Separable Gaussian filter, clamping at the borders
+fn gaussian_blur(img: &Image, sigma: f32) -> Image {
+    if sigma <= 0.0 {
+        return img.clone();
+    }
+    let radius = (3.0 * sigma).ceil() as i64;
+    let weights: Vec<f32> = (-radius..=radius)
+        .map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f32 = weights.iter().sum();
+
+    let pass = |src: &Image, dx: i64, dy: i64| -> Image {
+        let mut dst = Image::new(src.width, src.height);
+        for y in 0..src.height as i64 {
+            for x in 0..src.width as i64 {
+                let mut sum = Vec3::new(0.0, 0.0, 0.0);
+                for (k, w) in weights.iter().enumerate() {
+                    let o = k as i64 - radius;
+                    let sx = (x + o * dx).clamp(0, src.width as i64 - 1);
+                    let sy = (y + o * dy).clamp(0, src.height as i64 - 1);
+                    sum += *w * src.get(sx as u32, sy as u32);
+                }
+                dst.set(x as u32, y as u32, sum / total);
+            }
+        }
+        dst
+    };
+    pass(&pass(img, 1, 0), 0, 1)
+}
+
+// Linear sRGB (D65) to CIELAB
+fn to_lab(c: Vec3) -> Vec3 {
+    let x = 0.412_456 * c.x + 0.357_576 * c.y + 0.180_438 * c.z;
+    let y = 0.212_673 * c.x + 0.715_152 * c.y + 0.072_175 * c.z;
+    let z = 0.019_334 * c.x + 0.119_192 * c.y + 0.950_304 * c.z;
+    let f = |t: f32| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x / 0.950_489), f(y), f(z / 1.088_84));
+    Vec3::new(116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uniform(width: u32, height: u32, c: f32) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![Vec3::new(c, c, c); (width * height) as usize],
+        }
+    }
+
+    #[test]
+    fn rmse_of_uniform_images() {
+        let a = uniform(4, 3, 0.5);
+        let b = uniform(4, 3, 0.25);
+        assert_eq!(rmse(&a, &a).unwrap(), 0.0);
+        assert!((rmse(&a, &b).unwrap() - 0.25).abs() < 1e-6);
+        assert!(rmse(&a, &uniform(3, 4, 0.5)).is_err());
+    }
+
+    #[test]
+    fn psnr_and_relative_mse() {
+        let a = uniform(4, 3, 0.5);
+        let b = uniform(4, 3, 0.4);
+        assert!((mse(&a, &b).unwrap() - 0.01).abs() < 1e-6);
+        assert!((psnr(&a, &b).unwrap() - 20.0).abs() < 1e-3);
+        assert_eq!(psnr(&a, &a).unwrap(), f32::INFINITY);
+        let expected = 0.01 / (0.16 + RELATIVE_MSE_EPSILON);
+        assert!((relative_mse(&a, &b).unwrap() - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn ssim_of_structure() {
+        let mut checker = uniform(16, 16, 0.0);
+        for (i, p) in checker.pixels.iter_mut().enumerate() {
+            if (i / 4 + i / 64) % 2 == 0 {
+                *p = Vec3::new(1.0, 1.0, 1.0);
+            }
+        }
+        let gray = uniform(16, 16, 0.25);
+
+        assert!((ssim(&checker, &checker).unwrap() - 1.0).abs() < 1e-4);
+        assert!(ssim(&checker, &gray).unwrap() < 0.1);
+        let mut brighter = checker.clone();
+        for p in brighter.pixels.iter_mut() {
+            *p = *p * 0.9 + Vec3::new(0.05, 0.05, 0.05);
+        }
+        assert!(ssim(&checker, &brighter).unwrap() > 0.9);
+    }
+
+    #[test]
+    fn perceptual_error_ignores_fine_noise() {
+        let flat = uniform(16, 16, 0.5);
+        let mut noisy = flat.clone();
+        for (i, p) in noisy.pixels.iter_mut().enumerate() {
+            let d = if i % 2 == 0 { 0.1 } else { -0.1 };
+            *p += Vec3::new(d, d, d);
+        }
+        let darker = uniform(16, 16, 0.4);
+
+        assert_eq!(perceptual_error(&flat, &flat, 1.0).unwrap(), 0.0);
+        assert!(perceptual_error(&flat, &noisy, 1.0).unwrap() < 0.01);
+        assert!(perceptual_error(&flat, &darker, 1.0).unwrap() > 0.05);
+    }
+
+    #[test]
+    fn lab_of_white_and_black() {
+        let white = to_lab(Vec3::new(1.0, 1.0, 1.0));
+        assert!((white.x - 100.0).abs() < 0.01);
+        assert!(white.y.abs() < 0.01 && white.z.abs() < 0.01);
+        assert_eq!(to_lab(Vec3::new(0.0, 0.0, 0.0)).x, 0.0);
+    }
+}
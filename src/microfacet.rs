@@ -0,0 +1,296 @@
+use crate::vec3::Vec3;
+
+use std::f32::consts::PI;
+use std::ops::{Add, Div, Mul, Sub};
+
+// GGX (Trowbridge-Reitz) distribution of microfacet normals, with Smith's
+// height-correlated masking-shadowing. Directions are in the local frame of
+// the surface, the normal being +z.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrowbridgeReitz {
+    pub alpha: f32,
+}
+
+impl TrowbridgeReitz {
+    // Below this, the distribution is too peaked for floating point
+    const MIN_ALPHA: f32 = 1e-3;
+
+    // `roughness` in [0, 1], squared into the width of the distribution so
+    // that it varies evenly to the eye
+    pub fn from_roughness(roughness: f32) -> Self {
+        let r = roughness.clamp(0.0, 1.0);
+        TrowbridgeReitz {
+            alpha: (r * r).max(TrowbridgeReitz::MIN_ALPHA),
+        }
+    }
+
+    // Density of the microfacet normals `wm`, per unit projected area
+    pub fn d(&self, wm: &Vec3) -> f32 {
+        if wm.z <= 0.0 {
+            return 0.0;
+        }
+        let cos2 = wm.z * wm.z;
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        let a2 = self.alpha * self.alpha;
+        let e = 1.0 + tan2 / a2;
+        1.0 / (PI * a2 * cos2 * cos2 * e * e)
+    }
+
+    fn lambda(&self, w: &Vec3) -> f32 {
+        let cos2 = w.z * w.z;
+        if cos2 <= 0.0 {
+            return f32::INFINITY;
+        }
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        0.5 * ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0)
+    }
+
+    // Fraction of the microfacets visible from `w`
+    pub fn g1(&self, w: &Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Fraction of the microfacets visible from both `wo` and `wi`
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Density of the normals visible from `w`, above the surface, per unit
+    // solid angle
+    pub fn d_visible(&self, w: &Vec3, wm: &Vec3) -> f32 {
+        if w.z <= 0.0 {
+            return 0.0;
+        }
+        self.g1(w) / w.z * self.d(wm) * w.dot(wm).max(0.0)
+    }
+
+    // Normal visible from `w`, drawn with the density `d_visible` from the
+    // uniform numbers `u1`, `u2` (Heitz, "Sampling the GGX Distribution of
+    // Visible Normals")
+    pub fn sample_visible(&self, w: &Vec3, u1: f32, u2: f32) -> Vec3 {
+        // in the frame where the distribution is the hemisphere
+        let mut wh = Vec3::new(self.alpha * w.x, self.alpha * w.y, w.z).unit_vector();
+        if wh.z < 0.0 {
+            wh = -wh;
+        }
+        let t1 = if wh.z < 0.99999 {
+            Vec3::new(0.0, 0.0, 1.0).cross(&wh).unit_vector()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = wh.cross(&t1);
+
+        // point of the disk, warped to the visible half of it
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let px = r * phi.cos();
+        let h = (1.0 - px * px).max(0.0).sqrt();
+        let s = 0.5 * (1.0 + wh.z);
+        let py = (1.0 - s) * h + s * r * phi.sin();
+        let pz = (1.0 - px * px - py * py).max(0.0).sqrt();
+
+        let nh = px * t1 + py * t2 + pz * wh;
+        Vec3::new(self.alpha * nh.x, self.alpha * nh.y, nh.z.max(1e-6)).unit_vector()
+    }
+}
+
+// Microfacet normal turning `wo` into `wi` in the local frame, `wo` being
+// above the surface, through an interface with the index `eta` below it
+// relative to above. `None` if no microfacet facing `wo` does it.
+pub fn half_vector(wo: &Vec3, wi: &Vec3, eta: f32) -> Option<Vec3> {
+    let wm = if wi.z > 0.0 {
+        *wo + *wi
+    } else {
+        *wo + *wi * eta
+    };
+    if wm.squared_length() < 1e-12 || wo.z == 0.0 || wi.z == 0.0 {
+        return None;
+    }
+    let wm = wm.unit_vector();
+    let wm = if wm.z < 0.0 { -wm } else { wm };
+    // light cannot come through the back of a microfacet
+    if wm.dot(wo) <= 0.0 || wm.dot(wi) * wi.z <= 0.0 {
+        return None;
+    }
+    Some(wm)
+}
+
+// Direction of `wo` refracted by the microfacet `wm` into the relative index
+// `eta`, or `None` on total internal reflection
+pub fn refract(wo: &Vec3, wm: &Vec3, eta: f32) -> Option<Vec3> {
+    let cos_o = wo.dot(wm);
+    let sin2_t = (1.0 - cos_o * cos_o).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-*wo / eta + (cos_o / eta - cos_t) * *wm)
+}
+
+// Fraction of light reflected by a smooth interface, arriving at the cosine
+// `cos_i` from the side of index 1 onto the side of index `eta`. The cosine
+// is negative from the other side.
+pub fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let (cos_i, eta) = if cos_i < 0.0 {
+        (-cos_i, 1.0 / eta)
+    } else {
+        (cos_i, eta)
+    };
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        // total internal reflection
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parl = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perp = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (r_parl * r_parl + r_perp * r_perp)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Complex {
+    re: f32,
+    im: f32,
+}
+
+impl Complex {
+    fn new(re: f32, im: f32) -> Self {
+        Complex { re, im }
+    }
+
+    fn norm(self) -> f32 {
+        self.re * self.re + self.im * self.im
+    }
+
+    // Principal square root
+    fn sqrt(self) -> Complex {
+        let n = self.norm().sqrt();
+        if n == 0.0 {
+            return Complex::new(0.0, 0.0);
+        }
+        let t1 = (0.5 * (n + self.re.abs())).sqrt();
+        let t2 = 0.5 * self.im / t1;
+        if self.re >= 0.0 {
+            Complex::new(t1, t2)
+        } else {
+            Complex::new(t2.abs(), t1.copysign(self.im))
+        }
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, o: Complex) -> Complex {
+        Complex::new(self.re + o.re, self.im + o.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, o: Complex) -> Complex {
+        Complex::new(self.re - o.re, self.im - o.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, o: Complex) -> Complex {
+        Complex::new(
+            self.re * o.re - self.im * o.im,
+            self.re * o.im + self.im * o.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+    fn div(self, o: Complex) -> Complex {
+        let scale = 1.0 / o.norm();
+        Complex::new(
+            scale * (self.re * o.re + self.im * o.im),
+            scale * (self.im * o.re - self.re * o.im),
+        )
+    }
+}
+
+// Reflectance of a conductor of complex index `eta` + i `k`
+fn fresnel_conductor_channel(cos_i: f32, eta: f32, k: f32) -> f32 {
+    let cos_i = Complex::new(cos_i.clamp(0.0, 1.0), 0.0);
+    let eta = Complex::new(eta, k);
+    let sin2_i = Complex::new(1.0, 0.0) - cos_i * cos_i;
+    let sin2_t = sin2_i / (eta * eta);
+    let cos_t = (Complex::new(1.0, 0.0) - sin2_t).sqrt();
+    let r_parl = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perp = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (r_parl.norm() + r_perp.norm())
+}
+
+// Reflectance of a conductor per color channel
+pub fn fresnel_conductor(cos_i: f32, eta: &Vec3, k: &Vec3) -> Vec3 {
+    Vec3::new(
+        fresnel_conductor_channel(cos_i, eta.x, k.x),
+        fresnel_conductor_channel(cos_i, eta.y, k.y),
+        fresnel_conductor_channel(cos_i, eta.z, k.z),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Integral of `f` over the sphere, by the midpoint rule
+    fn integrate<F: Fn(&Vec3) -> f32>(f: F) -> f32 {
+        const N: usize = 400;
+        let mut sum = 0.0;
+        for i in 0..N {
+            for j in 0..N {
+                let cos_theta = -1.0 + 2.0 * (i as f32 + 0.5) / N as f32;
+                let phi = 2.0 * PI * (j as f32 + 0.5) / N as f32;
+                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                sum += f(&Vec3::new(
+                    sin_theta * phi.cos(),
+                    sin_theta * phi.sin(),
+                    cos_theta,
+                ));
+            }
+        }
+        sum * 4.0 * PI / (N * N) as f32
+    }
+
+    #[test]
+    fn normalized_distributions() {
+        let distribution = TrowbridgeReitz::from_roughness(0.6);
+        // the microfacets cover the surface
+        let projected = integrate(|wm| distribution.d(wm) * wm.z.max(0.0));
+        assert!((projected - 1.0).abs() < 0.01, "{}", projected);
+        for &theta in &[0.0f32, 0.7, 1.3] {
+            let w = Vec3::new(theta.sin(), 0.0, theta.cos());
+            let visible = integrate(|wm| distribution.d_visible(&w, wm));
+            assert!((visible - 1.0).abs() < 0.01, "{}", visible);
+
+            let wm = distribution.sample_visible(&w, 0.3, 0.8);
+            assert!((wm.length() - 1.0).abs() < 1e-5 && wm.z > 0.0 && wm.dot(&w) > 0.0);
+        }
+    }
+
+    #[test]
+    fn fresnel() {
+        // 4% off glass at normal incidence, total reflection from inside
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-4);
+        assert!((fresnel_dielectric(-1.0, 1.5) - 0.04).abs() < 1e-4);
+        assert_eq!(fresnel_dielectric(-0.3, 1.5), 1.0);
+        assert!(fresnel_dielectric(0.01, 1.5) > 0.9);
+
+        // a conductor with k = 0 is a dielectric
+        let dielectric =
+            fresnel_conductor(0.6, &Vec3::new(1.5, 1.5, 1.5), &Vec3::new(0.0, 0.0, 0.0));
+        assert!((dielectric.x - fresnel_dielectric(0.6, 1.5)).abs() < 1e-5);
+        // ((n - 1)² + k²) / ((n + 1)² + k²) at normal incidence
+        let r = fresnel_conductor(1.0, &Vec3::new(0.2, 0.2, 0.2), &Vec3::new(3.0, 3.0, 3.0));
+        assert!((r.x - (0.64 + 9.0) / (1.44 + 9.0)).abs() < 1e-5);
+        // and every conductor is a mirror at grazing angles
+        assert!(
+            fresnel_conductor(1e-4, &Vec3::new(0.2, 0.9, 1.1), &Vec3::new(3.9, 2.5, 2.1)).x > 0.99
+        );
+    }
+}
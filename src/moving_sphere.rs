@@ -0,0 +1,103 @@
+use crate::aabb::Aabb;
+use crate::hitable::{HitRecord, Hitable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sphere::hit_sphere;
+use crate::vec3::Vec3;
+
+use std::borrow::Borrow;
+use std::sync::Arc;
+
+// Sphere whose center moves linearly from `center0` at `time0` to `center1`
+// at `time1`, and keeps moving along the same line outside of that interval
+pub struct MovingSphere {
+    pub center0: Vec3,
+    pub center1: Vec3,
+    pub time0: f32,
+    pub time1: f32,
+    pub radius: f32,
+    pub material: Arc<dyn Material>,
+}
+
+impl MovingSphere {
+    pub fn new(
+        center0: Vec3,
+        center1: Vec3,
+        time0: f32,
+        time1: f32,
+        radius: f32,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        MovingSphere {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            material,
+        }
+    }
+
+    pub fn center(&self, time: f32) -> Vec3 {
+        if self.time1 == self.time0 {
+            return self.center0;
+        }
+        let s = (time - self.time0) / (self.time1 - self.time0);
+        self.center0 + s * (self.center1 - self.center0)
+    }
+}
+
+impl Hitable for MovingSphere {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        hit_sphere(
+            self.center(r.time),
+            self.radius,
+            self.material.borrow(),
+            r,
+            t_min,
+            t_max,
+        )
+    }
+
+    fn bounding_box(&self, time0: f32, time1: f32) -> Option<Aabb> {
+        let radius = self.radius.abs();
+        let r = Vec3::new(radius, radius, radius);
+        let (c0, c1) = (self.center(time0), self.center(time1));
+        Some(Aabb::new(c0 - r, c0 + r).surrounding(&Aabb::new(c1 - r, c1 + r)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::Bvh;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn hit_at_the_time_of_the_ray() {
+        let sphere = MovingSphere::new(
+            Vec3::new(0.0, 0.0, -5.0),
+            Vec3::new(2.0, 0.0, -5.0),
+            0.0,
+            1.0,
+            0.5,
+            Arc::new(Lambertian::new(0.5, 0.5, 0.5)),
+        );
+        let direction = Vec3::new(0.0, 0.0, -1.0);
+        let at = |x: f32, time: f32| Ray::with_time(Vec3::new(x, 0.0, 0.0), direction, time);
+        assert!(sphere.hit(&at(0.0, 0.0), 0.001, f32::MAX).is_some());
+        assert!(sphere.hit(&at(0.0, 1.0), 0.001, f32::MAX).is_none());
+        assert!(sphere.hit(&at(2.0, 1.0), 0.001, f32::MAX).is_some());
+        let rec = sphere.hit(&at(1.0, 0.5), 0.001, f32::MAX).unwrap();
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+
+        let bbox = sphere.bounding_box(0.0, 0.5).unwrap();
+        assert_eq!(bbox.min, Vec3::new(-0.5, -0.5, -5.5));
+        assert_eq!(bbox.max, Vec3::new(1.5, 0.5, -4.5));
+
+        // the hierarchy must cover the sphere over the whole interval
+        let bvh = Bvh::new(vec![Arc::new(sphere)], 0.0, 1.0);
+        assert!(bvh.hit(&at(2.0, 1.0), 0.001, f32::MAX).is_some());
+        assert!(bvh.hit(&at(2.0, 0.0), 0.001, f32::MAX).is_none());
+    }
+}
@@ -0,0 +1,215 @@
+use crate::hitable::HitRecord;
+use crate::material::{self, Interior, Material, ScatterRecord};
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::vec3::Vec3;
+
+use std::sync::Arc;
+
+// Source of the shading normal of a `NormalMapped` material
+pub enum Perturbation {
+    // Tangent-space normal map, the usual bluish images: red along `dpdu`,
+    // green along `dpdv` and blue along the normal, from -1 at 0 to 1 at 1
+    Normal(Arc<dyn Texture>),
+    // Grayscale height map, in units of `scale` along the normal
+    Bump {
+        height: Arc<dyn Texture>,
+        scale: f32,
+    },
+}
+
+// `base` shaded with a normal varying over the surface, to show details the
+// geometry does not have. The geometric normal stays in `HitRecord::normal`
+// for the objects; the base material sees the shading normal instead.
+pub struct NormalMapped {
+    pub base: Arc<dyn Material>,
+    pub perturbation: Perturbation,
+}
+
+// Smallest cosine between the shading normal and the outgoing direction
+const MIN_COSINE: f32 = 0.01;
+
+// Step of the finite differences of height maps, in texture coordinates
+const BUMP_DELTA: f32 = 1e-3;
+
+impl NormalMapped {
+    pub fn normal_map<T: Texture + 'static>(base: Arc<dyn Material>, map: T) -> Self {
+        NormalMapped {
+            base,
+            perturbation: Perturbation::Normal(Arc::new(map)),
+        }
+    }
+
+    pub fn bump_map<T: Texture + 'static>(base: Arc<dyn Material>, height: T, scale: f32) -> Self {
+        NormalMapped {
+            base,
+            perturbation: Perturbation::Bump {
+                height: Arc::new(height),
+                scale,
+            },
+        }
+    }
+
+    // Unit shading normal at `rec`, on the side of the geometric normal
+    pub fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        let n = rec.normal;
+        let shading = match &self.perturbation {
+            Perturbation::Normal(map) => {
+                let t = rec.dpdu - n * n.dot(&rec.dpdu);
+                let t = if t.squared_length() > 1e-12 {
+                    t.unit_vector()
+                } else {
+                    material::orthonormal_basis(&n).1
+                };
+                // the bitangent goes along `dpdv` whichever side the normal is
+                let b = n.cross(&t);
+                let b = if b.dot(&rec.dpdv) < 0.0 { -b } else { b };
+                let m = 2.0 * map.value(rec.u, rec.v, &rec.p) - Vec3::new(1.0, 1.0, 1.0);
+                m.x * t + m.y * b + m.z * n
+            }
+            Perturbation::Bump { height, scale } => {
+                let h = |du: f32, dv: f32| {
+                    let p = rec.p + du * rec.dpdu + dv * rec.dpdv;
+                    scale * height.value(rec.u + du, rec.v + dv, &p).x
+                };
+                let h0 = h(0.0, 0.0);
+                let dhdu = (h(BUMP_DELTA, 0.0) - h0) / BUMP_DELTA;
+                let dhdv = (h(0.0, BUMP_DELTA) - h0) / BUMP_DELTA;
+                let shading = (rec.dpdu + dhdu * n).cross(&(rec.dpdv + dhdv * n));
+                if shading.dot(&n) < 0.0 {
+                    -shading
+                } else {
+                    shading
+                }
+            }
+        };
+        if shading.squared_length() > 1e-12 {
+            shading.unit_vector()
+        } else {
+            n
+        }
+    }
+
+    // `rec` with the shading normal, seen from `wo`
+    fn shade<'a>(&self, rec: &HitRecord<'a>, wo: &Vec3) -> HitRecord<'a> {
+        let mut n = self.shading_normal(rec);
+        // A shading normal may turn away from a direction the geometry faces,
+        // which would look black: it is tilted back towards it.
+        let side = if wo.dot(&rec.normal) < 0.0 { -1.0 } else { 1.0 };
+        let cosine = side * wo.dot(&n);
+        if cosine < MIN_COSINE {
+            n = (n + (side * (MIN_COSINE - cosine)) * *wo).unit_vector();
+        }
+        HitRecord { normal: n, ..*rec }
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let wo = -r_in.direction.unit_vector();
+        self.base.scatter(r_in, &self.shade(rec, &wo))
+    }
+
+    fn interior(&self) -> Option<Interior> {
+        self.base.interior()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive()
+    }
+
+    fn scatter_between(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        n_from: f32,
+        n_to: f32,
+    ) -> Option<ScatterRecord> {
+        let wo = -r_in.direction.unit_vector();
+        self.base
+            .scatter_between(r_in, &self.shade(rec, &wo), n_from, n_to)
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        self.base.eval(&self.shade(rec, wo), wo, wi)
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f32 {
+        self.base.pdf(&self.shade(rec, wo), wo, wi)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Vec3 {
+        self.base.emitted(rec)
+    }
+
+    fn alpha_test(&self, rec: &HitRecord) -> bool {
+        self.base.alpha_test(rec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn record(material: &dyn Material) -> HitRecord<'_> {
+        // tangents twice as long as the texture coordinates
+        HitRecord {
+            u: 0.5,
+            v: 0.5,
+            dpdu: Vec3::new(2.0, 0.0, 0.0),
+            dpdv: Vec3::new(0.0, 2.0, 0.0),
+            ..HitRecord::facing_z(material)
+        }
+    }
+
+    // Height rising along u, as a texture
+    struct Ramp;
+
+    impl Texture for Ramp {
+        fn value(&self, u: f32, _v: f32, _p: &Vec3) -> Vec3 {
+            Vec3::new(u, u, u)
+        }
+    }
+
+    #[test]
+    fn shading_normals() {
+        let base: Arc<dyn Material> = Arc::new(Lambertian::new(0.5, 0.5, 0.5));
+
+        // pointing along dpdu, in tangent space
+        let mapped = NormalMapped::normal_map(base.clone(), Vec3::new(0.8, 0.5, 0.9));
+        let n = mapped.shading_normal(&record(&mapped));
+        assert!((n - Vec3::new(0.6, 0.0, 0.8)).length() < 1e-5, "{:?}", n);
+
+        // a slope of 3 / 4 over a length of 2 per unit of u: the normal leans
+        // back from it
+        let bumped = NormalMapped::bump_map(base.clone(), Ramp, 1.5);
+        let n = bumped.shading_normal(&record(&bumped));
+        assert!((n - Vec3::new(-0.6, 0.0, 0.8)).length() < 1e-3, "{:?}", n);
+
+        // the same seen from the back of the surface
+        let mut rec = record(&mapped);
+        rec.normal = -rec.normal;
+        let n = mapped.shading_normal(&rec);
+        assert!((n - Vec3::new(0.6, 0.0, -0.8)).length() < 1e-5, "{:?}", n);
+    }
+
+    #[test]
+    fn shading_normals_stay_towards_the_viewer() {
+        let base: Arc<dyn Material> = Arc::new(Lambertian::new(0.5, 0.5, 0.5));
+        let mapped = NormalMapped::normal_map(base, Vec3::new(0.8, 0.5, 0.9));
+        let rec = record(&mapped);
+
+        // grazing from the side the normal leans away from
+        let wo = Vec3::new(-0.99, 0.0, 0.141).unit_vector();
+        assert!(wo.dot(&mapped.shading_normal(&rec)) < 0.0);
+        let shaded = mapped.shade(&rec, &wo);
+        assert!(wo.dot(&shaded.normal) > 0.0);
+        assert!((shaded.normal.length() - 1.0).abs() < 1e-5);
+        assert!(mapped.eval(&rec, &wo, &Vec3::new(0.0, 0.0, 1.0)).x > 0.0);
+
+        // and from straight on, nothing changes
+        let wo = Vec3::new(0.0, 0.0, 1.0);
+        assert_eq!(mapped.shade(&rec, &wo).normal, mapped.shading_normal(&rec));
+    }
+}
@@ -0,0 +1,127 @@
+use crate::vec3::Vec3;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+const POINT_COUNT: usize = 256;
+
+// Gradient noise with random unit gradients on the integer lattice,
+// smoothly interpolated in between. Values are in about [-1, 1].
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    // The same seed gives the same noise
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let gradients = (0..POINT_COUNT)
+            .map(|_| loop {
+                let v = Vec3::new(
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(-1.0, 1.0),
+                );
+                let len2 = v.squared_length();
+                if len2 > 1e-4 && len2 <= 1.0 {
+                    break v / len2.sqrt();
+                }
+            })
+            .collect();
+        let mut permutation = || {
+            let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+            p.shuffle(&mut rng);
+            p
+        };
+        Perlin {
+            perm_x: permutation(),
+            perm_y: permutation(),
+            perm_z: permutation(),
+            gradients,
+        }
+    }
+
+    pub fn noise(&self, p: &Vec3) -> f32 {
+        let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (u, v, w) = (p.x - fx, p.y - fy, p.z - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+        // Hermite smoothing of the weights
+        let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+        let (uu, vv, ww) = (smooth(u), smooth(v), smooth(w));
+
+        let mut sum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let index = self.perm_x[((i + di) & 255) as usize]
+                        ^ self.perm_y[((j + dj) & 255) as usize]
+                        ^ self.perm_z[((k + dk) & 255) as usize];
+                    let weight = Vec3::new(u - di as f32, v - dj as f32, w - dk as f32);
+                    let (a, b, c) = (di as f32, dj as f32, dk as f32);
+                    sum += (a * uu + (1.0 - a) * (1.0 - uu))
+                        * (b * vv + (1.0 - b) * (1.0 - vv))
+                        * (c * ww + (1.0 - c) * (1.0 - ww))
+                        * self.gradients[index].dot(&weight);
+                }
+            }
+        }
+        sum
+    }
+
+    // Sum of `octaves` layers of noise, each of double frequency and half
+    // amplitude (fractional Brownian motion)
+    pub fn fbm(&self, p: &Vec3, octaves: u32) -> f32 {
+        let mut sum = 0.0;
+        let mut point = *p;
+        let mut amplitude = 1.0;
+        for _ in 0..octaves {
+            sum += amplitude * self.noise(&point);
+            amplitude *= 0.5;
+            point = point * 2.0;
+        }
+        sum
+    }
+
+    // Like `fbm` with the absolute value of each layer, for billowy shapes
+    pub fn turbulence(&self, p: &Vec3, octaves: u32) -> f32 {
+        let mut sum = 0.0;
+        let mut point = *p;
+        let mut amplitude = 1.0;
+        for _ in 0..octaves {
+            sum += amplitude * self.noise(&point).abs();
+            amplitude *= 0.5;
+            point = point * 2.0;
+        }
+        sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smooth_and_reproducible() {
+        let a = Perlin::new(1);
+        let b = Perlin::new(1);
+        let p = Vec3::new(1.3, -2.7, 0.4);
+        assert_eq!(a.noise(&p), b.noise(&p));
+        assert_ne!(a.noise(&p), Perlin::new(2).noise(&p));
+
+        // zero on the lattice, continuous in between
+        assert_eq!(a.noise(&Vec3::new(3.0, 1.0, -2.0)), 0.0);
+        let q = p + Vec3::new(1e-3, 0.0, 0.0);
+        assert!((a.noise(&p) - a.noise(&q)).abs() < 0.01);
+
+        let mut max: f32 = 0.0;
+        for i in 0..1000 {
+            let x = i as f32 * 0.137;
+            max = max.max(a.fbm(&Vec3::new(x, 0.5 * x, 0.3), 4).abs());
+        }
+        assert!(max > 0.1 && max < 2.0);
+    }
+}
@@ -0,0 +1,439 @@
+use crate::hitable::HitRecord;
+use crate::material::{self, Frame, Material, ScatterRecord};
+use crate::microfacet::{self, TrowbridgeReitz};
+use crate::random;
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::vec3::Vec3;
+
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+// One material for most surfaces, after the "principled" BRDF of Disney
+// (Burley, "Physically Based Shading at Disney"), so that materials exported
+// from modeling tools map to it. It combines a diffuse lobe with sheen, a
+// GGX specular lobe, a GGX clear coat and a rough dielectric lobe for the
+// transmission, and may glow. Every parameter is a texture; those which are a
+// number take the first channel, in [0, 1].
+//
+//     let varnished_wood = Principled::new(wood_texture)
+//         .with_roughness(0.6)
+//         .with_clearcoat(1.0)
+//         .with_clearcoat_roughness(0.1);
+//
+// Refraction is against the void: the object is not a medium for nesting.
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    // 0 for dielectrics, 1 for metals, which reflect the base color
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    // reflectance of dielectrics, 0.5 being 4% at normal incidence
+    pub specular: Arc<dyn Texture>,
+    // how much the reflection of dielectrics takes the hue of the base color
+    pub specular_tint: Arc<dyn Texture>,
+    // extra reflection at grazing angles, for cloth
+    pub sheen: Arc<dyn Texture>,
+    pub sheen_tint: Arc<dyn Texture>,
+    // strength of a white specular layer on top, like varnish
+    pub clearcoat: Arc<dyn Texture>,
+    pub clearcoat_roughness: Arc<dyn Texture>,
+    // fraction of the light going through the surface, as in glass
+    pub transmission: Arc<dyn Texture>,
+    // index of refraction of the transmission lobe
+    pub ior: f32,
+    // light given off, in radiance
+    pub emission: Arc<dyn Texture>,
+}
+
+// Parameters at a point of the surface and the lobes they give
+struct Lobes {
+    base: Vec3,
+    // weights of the opaque dielectric, metallic and transmissive parts
+    diffuse: f32,
+    metallic: f32,
+    glass: f32,
+    sheen: Vec3,
+    // reflectance at normal incidence of the opaque dielectric part
+    f0: Vec3,
+    clearcoat: f32,
+    roughness: f32,
+    specular: TrowbridgeReitz,
+    coat: TrowbridgeReitz,
+    // index below the surface relative to above, `wo` being above
+    eta: f32,
+}
+
+const DIFFUSE: usize = 0;
+const SPECULAR: usize = 1;
+const CLEARCOAT: usize = 2;
+const GLASS: usize = 3;
+
+fn luminance(c: &Vec3) -> f32 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+fn lerp(a: Vec3, b: Vec3, t: f32) -> Vec3 {
+    (1.0 - t) * a + t * b
+}
+
+fn schlick_weight(cosine: f32) -> f32 {
+    (1.0 - cosine).clamp(0.0, 1.0).powi(5)
+}
+
+fn schlick(f0: Vec3, cosine: f32) -> Vec3 {
+    lerp(f0, Vec3::new(1.0, 1.0, 1.0), schlick_weight(cosine))
+}
+
+impl Principled {
+    // Plain diffuse surface of the color `base_color`, which the other
+    // parameters change
+    pub fn new<T: Texture + 'static>(base_color: T) -> Self {
+        Principled {
+            base_color: Arc::new(base_color),
+            metallic: Arc::new(0.0),
+            roughness: Arc::new(0.5),
+            specular: Arc::new(0.5),
+            specular_tint: Arc::new(0.0),
+            sheen: Arc::new(0.0),
+            sheen_tint: Arc::new(0.5),
+            clearcoat: Arc::new(0.0),
+            clearcoat_roughness: Arc::new(0.1),
+            transmission: Arc::new(0.0),
+            ior: 1.5,
+            emission: Arc::new(0.0),
+        }
+    }
+
+    pub fn with_metallic<T: Texture + 'static>(mut self, metallic: T) -> Self {
+        self.metallic = Arc::new(metallic);
+        self
+    }
+
+    pub fn with_roughness<T: Texture + 'static>(mut self, roughness: T) -> Self {
+        self.roughness = Arc::new(roughness);
+        self
+    }
+
+    pub fn with_specular<T: Texture + 'static>(mut self, specular: T) -> Self {
+        self.specular = Arc::new(specular);
+        self
+    }
+
+    pub fn with_specular_tint<T: Texture + 'static>(mut self, tint: T) -> Self {
+        self.specular_tint = Arc::new(tint);
+        self
+    }
+
+    pub fn with_sheen<T: Texture + 'static>(mut self, sheen: T) -> Self {
+        self.sheen = Arc::new(sheen);
+        self
+    }
+
+    pub fn with_sheen_tint<T: Texture + 'static>(mut self, tint: T) -> Self {
+        self.sheen_tint = Arc::new(tint);
+        self
+    }
+
+    pub fn with_clearcoat<T: Texture + 'static>(mut self, clearcoat: T) -> Self {
+        self.clearcoat = Arc::new(clearcoat);
+        self
+    }
+
+    pub fn with_clearcoat_roughness<T: Texture + 'static>(mut self, roughness: T) -> Self {
+        self.clearcoat_roughness = Arc::new(roughness);
+        self
+    }
+
+    pub fn with_transmission<T: Texture + 'static>(mut self, transmission: T, ior: f32) -> Self {
+        self.transmission = Arc::new(transmission);
+        self.ior = ior;
+        self
+    }
+
+    pub fn with_emission<T: Texture + 'static>(mut self, emission: T) -> Self {
+        self.emission = Arc::new(emission);
+        self
+    }
+
+    // Frame with the normal on the side of `wo`, and the lobes there
+    fn lobes(&self, rec: &HitRecord, wo: &Vec3) -> (Frame, Lobes) {
+        let color = |t: &Arc<dyn Texture>| t.value(rec.u, rec.v, &rec.p);
+        let scalar = |t: &Arc<dyn Texture>| color(t).x.clamp(0.0, 1.0);
+
+        let base = color(&self.base_color);
+        let metallic = scalar(&self.metallic);
+        let transmission = scalar(&self.transmission);
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let tint = if luminance(&base) > 0.0 {
+            base / luminance(&base)
+        } else {
+            white
+        };
+        let specular = scalar(&self.specular) * 0.08;
+        let dielectric_f0 = specular * lerp(white, tint, scalar(&self.specular_tint));
+        let roughness = scalar(&self.roughness);
+
+        let outward = rec.outward_normal();
+        let outside = wo.dot(&outward) >= 0.0;
+        let frame = Frame::new(&if outside { outward } else { -outward });
+        let lobes = Lobes {
+            base,
+            diffuse: (1.0 - metallic) * (1.0 - transmission),
+            metallic,
+            glass: (1.0 - metallic) * transmission,
+            sheen: scalar(&self.sheen) * lerp(white, tint, scalar(&self.sheen_tint)),
+            f0: dielectric_f0,
+            clearcoat: 0.25 * scalar(&self.clearcoat),
+            roughness,
+            specular: TrowbridgeReitz::from_roughness(roughness),
+            coat: TrowbridgeReitz::from_roughness(scalar(&self.clearcoat_roughness)),
+            eta: if outside { self.ior } else { 1.0 / self.ior },
+        };
+        (frame, lobes)
+    }
+}
+
+impl Lobes {
+    // Reflectance of the specular lobe, whose surface is opaque
+    fn specular_fresnel(&self, cosine: f32) -> Vec3 {
+        self.diffuse * schlick(self.f0, cosine) + self.metallic * schlick(self.base, cosine)
+    }
+
+    // Probabilities of sampling each lobe, about their share of the
+    // scattered light
+    fn probabilities(&self, wo: &Vec3) -> [f32; 4] {
+        let mut p = [
+            self.diffuse * (luminance(&self.base) + luminance(&self.sheen)),
+            luminance(&self.specular_fresnel(wo.z)),
+            self.clearcoat * schlick(Vec3::new(0.04, 0.04, 0.04), wo.z).x,
+            self.glass,
+        ];
+        let sum: f32 = p.iter().sum();
+        if sum > 0.0 {
+            for x in &mut p {
+                *x /= sum;
+            }
+        }
+        p
+    }
+
+    // BSDF times the cosine, in the local frame with `wo` above
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        let zero = Vec3::new(0.0, 0.0, 0.0);
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return zero;
+        }
+        // the transmissive part is a rough dielectric, tinted inside
+        let glass = match microfacet::half_vector(wo, wi, self.eta) {
+            Some(wm) if self.glass > 0.0 => {
+                let reflectance = microfacet::fresnel_dielectric(wo.dot(&wm), self.eta);
+                let dg = self.specular.d(&wm) * self.specular.g(wo, wi);
+                if wi.z > 0.0 {
+                    let f = self.glass * reflectance * dg / (4.0 * wo.z);
+                    Vec3::new(f, f, f)
+                } else {
+                    let denom = wi.dot(&wm) + wo.dot(&wm) / self.eta;
+                    let btdf = (1.0 - reflectance)
+                        * dg
+                        * (wi.dot(&wm) * wo.dot(&wm) / (wo.z * denom * denom)).abs();
+                    self.base * (self.glass * btdf)
+                }
+            }
+            _ => zero,
+        };
+        if wi.z < 0.0 {
+            return glass;
+        }
+
+        let wh = (*wo + *wi).unit_vector();
+        let cos_d = wi.dot(&wh);
+        // Burley's diffuse, brighter at grazing angles when rough
+        let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+        let fd = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z))
+            * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z));
+        let diffuse =
+            (self.base * (fd / PI) + self.sheen * schlick_weight(cos_d)) * (self.diffuse * wi.z);
+
+        let specular = self.specular_fresnel(cos_d)
+            * (self.specular.d(&wh) * self.specular.g(wo, wi) / (4.0 * wo.z));
+        let coat = self.clearcoat
+            * schlick(Vec3::new(0.04, 0.04, 0.04), cos_d).x
+            * self.coat.d(&wh)
+            * self.coat.g(wo, wi)
+            / (4.0 * wo.z);
+        diffuse + specular + Vec3::new(coat, coat, coat) + glass
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f32 {
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return 0.0;
+        }
+        let p = self.probabilities(wo);
+        let glass = match microfacet::half_vector(wo, wi, self.eta) {
+            Some(wm) => {
+                let reflectance = microfacet::fresnel_dielectric(wo.dot(&wm), self.eta);
+                let visible = p[GLASS] * self.specular.d_visible(wo, &wm);
+                if wi.z > 0.0 {
+                    visible * reflectance / (4.0 * wo.dot(&wm))
+                } else {
+                    let denom = wi.dot(&wm) + wo.dot(&wm) / self.eta;
+                    visible * (1.0 - reflectance) * wi.dot(&wm).abs() / (denom * denom)
+                }
+            }
+            None => 0.0,
+        };
+        if wi.z < 0.0 {
+            return glass;
+        }
+        let wh = (*wo + *wi).unit_vector();
+        let reflection = 4.0 * wo.dot(&wh);
+        p[DIFFUSE] * wi.z / PI
+            + p[SPECULAR] * self.specular.d_visible(wo, &wh) / reflection
+            + p[CLEARCOAT] * self.coat.d_visible(wo, &wh) / reflection
+            + glass
+    }
+
+    fn sample(&self, wo: &Vec3) -> Option<Vec3> {
+        let p = self.probabilities(wo);
+        let mut u = random::next_f32();
+        let mut lobe = DIFFUSE;
+        while lobe < GLASS && u >= p[lobe] {
+            u -= p[lobe];
+            lobe += 1;
+        }
+        let (u1, u2) = (random::next_f32(), random::next_f32());
+        let (wi, reflected) = match lobe {
+            DIFFUSE => {
+                let d = Vec3::new(0.0, 0.0, 1.0) + material::random_unit_vector();
+                if d.squared_length() < 1e-12 {
+                    return None;
+                }
+                (d.unit_vector(), true)
+            }
+            SPECULAR => {
+                let wm = self.specular.sample_visible(wo, u1, u2);
+                (material::reflect(-*wo, wm), true)
+            }
+            CLEARCOAT => (
+                material::reflect(-*wo, self.coat.sample_visible(wo, u1, u2)),
+                true,
+            ),
+            _ => {
+                let wm = self.specular.sample_visible(wo, u1, u2);
+                let reflectance = microfacet::fresnel_dielectric(wo.dot(&wm), self.eta);
+                if random::next_f32() < reflectance {
+                    (material::reflect(-*wo, wm), true)
+                } else {
+                    (microfacet::refract(wo, &wm, self.eta)?, false)
+                }
+            }
+        };
+        // microfacets may send it to the other side
+        if (wi.z > 0.0) != reflected {
+            return None;
+        }
+        Some(wi)
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let wo = -r_in.direction.unit_vector();
+        let (frame, lobes) = self.lobes(rec, &wo);
+        let wo = frame.to_local(&wo);
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let wi = lobes.sample(&wo)?;
+        // all the lobes which could have given `wi` count
+        let pdf = lobes.pdf(&wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(ScatterRecord {
+            attenuation: lobes.eval(&wo, &wi) / pdf,
+            scattered: Ray::with_time(rec.p, frame.to_world(&wi), r_in.time),
+        })
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        let (frame, lobes) = self.lobes(rec, wo);
+        lobes.eval(&frame.to_local(wo), &frame.to_local(wi))
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f32 {
+        let (frame, lobes) = self.lobes(rec, wo);
+        lobes.pdf(&frame.to_local(wo), &frame.to_local(wi))
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Vec3 {
+        self.emission.value(rec.u, rec.v, &rec.p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smooth_limits() {
+        random::seed(5);
+        let color = Vec3::new(0.9, 0.6, 0.3);
+        let r_in = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+
+        // a smooth metal is a mirror of the base color
+        let metal = Principled::new(color)
+            .with_metallic(1.0)
+            .with_roughness(0.0);
+        let s = metal.scatter(&r_in, &HitRecord::facing_z(&metal)).unwrap();
+        assert!((s.attenuation - color).length() < 1e-3);
+        assert!((s.scattered.direction.unit_vector() - Vec3::new(0.0, 0.0, 1.0)).length() < 0.05);
+
+        // smooth glass reflects white and refracts straight through, tinted
+        let glass = Principled::new(color)
+            .with_roughness(0.0)
+            .with_transmission(1.0, 1.5);
+        for _ in 0..100 {
+            let s = glass.scatter(&r_in, &HitRecord::facing_z(&glass)).unwrap();
+            let d = s.scattered.direction.unit_vector();
+            if d.z > 0.0 {
+                assert!((s.attenuation - Vec3::new(1.0, 1.0, 1.0)).length() < 1e-3);
+            } else {
+                assert!((s.attenuation - color).length() < 1e-3);
+                assert!((d - Vec3::new(0.0, 0.0, -1.0)).length() < 0.05);
+            }
+        }
+    }
+
+    #[test]
+    fn textured_parameters() {
+        use crate::texture::Checker;
+
+        // metallic in one cell of the checker board only
+        let metallic = Checker::new(Arc::new(1.0), Arc::new(0.0), 1.0);
+        let material = Principled::new(Vec3::new(0.5, 0.5, 0.5))
+            .with_metallic(metallic)
+            .with_emission(Vec3::new(2.0, 1.0, 0.0));
+        let mut rec = HitRecord::facing_z(&material);
+        let wo = Vec3::new(0.0, 0.6, 0.8);
+        let wi = Vec3::new(0.0, -0.6, 0.8);
+        let shiny = material.eval(&rec, &wo, &wi);
+        rec.p = Vec3::new(-0.5, 0.5, 0.5);
+        let matte = material.eval(&rec, &wo, &wi);
+        assert!(shiny.x > 2.0 * matte.x, "{:?} {:?}", shiny, matte);
+
+        assert_eq!(material.emitted(&rec), Vec3::new(2.0, 1.0, 0.0));
+
+        // a textured specular with a plain tint
+        let specular = Checker::new(Arc::new(1.0), Arc::new(0.0), 1.0);
+        let material = Principled::new(Vec3::new(0.5, 0.5, 0.5))
+            .with_roughness(0.2)
+            .with_specular(specular)
+            .with_specular_tint(1.0);
+        let mut rec = HitRecord::facing_z(&material);
+        let shiny = material.eval(&rec, &wo, &wi);
+        rec.p = Vec3::new(-0.5, 0.5, 0.5);
+        let matte = material.eval(&rec, &wo, &wi);
+        assert!(shiny.x > matte.x, "{:?} {:?}", shiny, matte);
+    }
+}
@@ -0,0 +1,107 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const BAR_WIDTH: usize = 40;
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+// Terminal progress bar showing the pixels done, the ETA and the ray throughput.
+// It can be shared between rayon workers; the bar is redrawn on stderr at most
+// every `REDRAW_INTERVAL`.
+pub struct ProgressBar {
+    total: u64,
+    done: AtomicU64,
+    rays: AtomicU64,
+    start: Instant,
+    last_draw: Mutex<Option<Instant>>,
+}
+
+impl ProgressBar {
+    pub fn new(total: u64) -> Self {
+        ProgressBar {
+            total,
+            done: AtomicU64::new(0),
+            rays: AtomicU64::new(0),
+            start: Instant::now(),
+            last_draw: Mutex::new(None),
+        }
+    }
+
+    // Record that `pixels_done` pixels are done, which traced `rays` rays.
+    // Updates may arrive out of order from several threads.
+    pub fn update(&self, pixels_done: u64, rays: u64) {
+        self.done.fetch_max(pixels_done, Ordering::Relaxed);
+        self.rays.fetch_max(rays, Ordering::Relaxed);
+
+        // Skip the redraw if another thread is drawing
+        if let Ok(mut last_draw) = self.last_draw.try_lock() {
+            let now = Instant::now();
+            let due = match *last_draw {
+                Some(t) => now - t >= REDRAW_INTERVAL,
+                None => true,
+            };
+            if due {
+                *last_draw = Some(now);
+                self.draw(false);
+            }
+        }
+    }
+
+    // Draw the final state of the bar and end the line.
+    pub fn finish(&self) {
+        self.draw(true);
+    }
+
+    pub fn done(&self) -> u64 {
+        self.done.load(Ordering::Relaxed)
+    }
+
+    // Estimated time left, from the average speed so far
+    pub fn eta(&self) -> Option<Duration> {
+        let done = self.done();
+        if done == 0 {
+            return None;
+        }
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let left = self.total.saturating_sub(done) as f64;
+        Some(Duration::from_secs_f64(elapsed * left / done as f64))
+    }
+
+    fn draw(&self, last: bool) {
+        let done = self.done().min(self.total);
+        let fraction = if self.total > 0 {
+            done as f64 / self.total as f64
+        } else {
+            1.0
+        };
+        let filled = (fraction * BAR_WIDTH as f64) as usize;
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let rays_per_sec = if elapsed > 0.0 {
+            self.rays.load(Ordering::Relaxed) as f64 / elapsed
+        } else {
+            0.0
+        };
+        let eta = match self.eta() {
+            Some(eta) if !last => format!("ETA {:.1}s", eta.as_secs_f64()),
+            _ => format!("in {:.1}s", elapsed),
+        };
+
+        let mut stderr = io::stderr();
+        let _ = write!(
+            stderr,
+            "\r[{}{}] {:5.1}% {}/{} px  {}  {:.2} Mrays/s ",
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            100.0 * fraction,
+            done,
+            self.total,
+            eta,
+            rays_per_sec / 1.0e6
+        );
+        if last {
+            let _ = writeln!(stderr);
+        }
+        let _ = stderr.flush();
+    }
+}
@@ -0,0 +1,266 @@
+use crate::aabb::Aabb;
+use crate::hitable::{HitRecord, Hitable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+use std::borrow::Borrow;
+use std::sync::Arc;
+
+// Parallelogram with a corner at `q` and sides `u` and `v`. The normal is
+// along u × v, and the texture coordinates go from 0 to 1 along the sides.
+pub struct Quad {
+    pub q: Vec3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub material: Arc<dyn Material>,
+    normal: Vec3,
+    // plane of the quad: normal · p = d
+    d: f32,
+    // n / (n · n), to get the coordinates of a point along `u` and `v`
+    w: Vec3,
+}
+
+impl Quad {
+    pub fn new(q: Vec3, u: Vec3, v: Vec3, material: Arc<dyn Material>) -> Self {
+        let n = u.cross(&v);
+        let normal = n.unit_vector();
+        Quad {
+            q,
+            u,
+            v,
+            material,
+            normal,
+            d: normal.dot(&q),
+            w: n / n.dot(&n),
+        }
+    }
+
+    // Rectangle x0..x1, y0..y1 in the plane z = k, facing +z
+    pub fn xy(x0: f32, x1: f32, y0: f32, y1: f32, k: f32, material: Arc<dyn Material>) -> Self {
+        Quad::new(
+            Vec3::new(x0, y0, k),
+            Vec3::new(x1 - x0, 0.0, 0.0),
+            Vec3::new(0.0, y1 - y0, 0.0),
+            material,
+        )
+    }
+
+    // Rectangle x0..x1, z0..z1 in the plane y = k, facing +y
+    pub fn xz(x0: f32, x1: f32, z0: f32, z1: f32, k: f32, material: Arc<dyn Material>) -> Self {
+        Quad::new(
+            Vec3::new(x0, k, z0),
+            Vec3::new(0.0, 0.0, z1 - z0),
+            Vec3::new(x1 - x0, 0.0, 0.0),
+            material,
+        )
+    }
+
+    // Rectangle y0..y1, z0..z1 in the plane x = k, facing +x
+    pub fn yz(y0: f32, y1: f32, z0: f32, z1: f32, k: f32, material: Arc<dyn Material>) -> Self {
+        Quad::new(
+            Vec3::new(k, y0, z0),
+            Vec3::new(0.0, y1 - y0, 0.0),
+            Vec3::new(0.0, 0.0, z1 - z0),
+            material,
+        )
+    }
+
+    // Same quad facing the other way
+    pub fn flipped(&self) -> Self {
+        Quad::new(self.q + self.u, -self.u, self.v, self.material.clone())
+    }
+}
+
+impl Hitable for Quad {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let denom = self.normal.dot(&r.direction);
+        // ray parallel to the plane
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let t = (self.d - self.normal.dot(&r.origin)) / denom;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+
+        let p = r.point_at_parameter(t);
+        let planar = p - self.q;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        let mut rec = HitRecord {
+            t,
+            p,
+            normal: self.normal,
+            front_face: true,
+            u: alpha,
+            v: beta,
+            dpdu: self.u,
+            dpdv: self.v,
+            material: self.material.borrow(),
+        };
+        rec.set_face_normal(r, self.normal);
+        if !rec.material.alpha_test(&rec) {
+            return None;
+        }
+        Some(rec)
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32) -> Option<Aabb> {
+        let diagonal1 = Aabb::new(self.q, self.q + self.u + self.v);
+        let diagonal2 = Aabb::new(self.q + self.u, self.q + self.v);
+        Some(diagonal1.surrounding(&diagonal2).pad(1e-4))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(0.5, 0.5, 0.5))
+    }
+
+    #[test]
+    fn hit_parallelogram() {
+        let quad = Quad::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            material(),
+        );
+        let down = Vec3::new(0.0, 0.0, -1.0);
+        let rec = quad
+            .hit(&Ray::new(Vec3::new(2.0, 0.5, 1.0), down), 0.0, f32::MAX)
+            .unwrap();
+        assert_eq!(rec.t, 1.0);
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert!((rec.u - 0.75).abs() < 1e-6 && (rec.v - 0.5).abs() < 1e-6);
+
+        // outside of the slanted side
+        assert!(quad
+            .hit(&Ray::new(Vec3::new(0.2, 0.5, 1.0), down), 0.0, f32::MAX)
+            .is_none());
+        // parallel to the plane
+        let along = Ray::new(Vec3::new(-1.0, 0.5, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(quad.hit(&along, 0.0, f32::MAX).is_none());
+    }
+
+    #[test]
+    fn axis_aligned_rectangles() {
+        let xz = Quad::xz(-1.0, 1.0, -2.0, 2.0, 3.0, material());
+        assert_eq!(xz.normal, Vec3::new(0.0, 1.0, 0.0));
+        let rec = xz
+            .hit(
+                &Ray::new(Vec3::new(0.5, 0.0, 1.0), Vec3::new(0.0, 1.0, 0.0)),
+                0.0,
+                f32::MAX,
+            )
+            .unwrap();
+        assert_eq!(rec.p, Vec3::new(0.5, 3.0, 1.0));
+
+        let bbox = xz.bounding_box(0.0, 0.0).unwrap();
+        assert_eq!((bbox.min.x, bbox.max.z), (-1.0, 2.0));
+        assert!(bbox.max.y > bbox.min.y);
+
+        assert_eq!(Quad::xy(0.0, 1.0, 0.0, 1.0, 0.0, material()).normal.z, 1.0);
+        assert_eq!(Quad::yz(0.0, 1.0, 0.0, 1.0, 0.0, material()).normal.x, 1.0);
+        assert_eq!(xz.flipped().normal, Vec3::new(0.0, -1.0, 0.0));
+    }
+
+    #[test]
+    fn one_sided_quads() {
+        use crate::material::{BackFace, Sided};
+
+        let quad = |back| {
+            let material = Arc::new(Sided::new(material(), back));
+            Quad::xy(0.0, 1.0, 0.0, 1.0, 0.0, material)
+        };
+        let front = Ray::new(Vec3::new(0.5, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let back = Ray::new(Vec3::new(0.5, 0.5, -1.0), Vec3::new(0.0, 0.0, 1.0));
+
+        // the normal faces the ray on both sides
+        let black = quad(BackFace::Black);
+        let rec = black.hit(&back, 0.0, f32::MAX).unwrap();
+        assert!(!rec.front_face);
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, -1.0));
+        // black from the back only
+        assert!(rec.material.scatter(&back, &rec).is_none());
+        let rec = black.hit(&front, 0.0, f32::MAX).unwrap();
+        assert!(rec.front_face);
+        assert!(rec.material.scatter(&front, &rec).is_some());
+
+        // and culled
+        let culled = quad(BackFace::Culled);
+        assert!(culled.hit(&back, 0.0, f32::MAX).is_none());
+        assert!(culled.hit(&front, 0.0, f32::MAX).is_some());
+        assert!(culled.flipped().hit(&front, 0.0, f32::MAX).is_none());
+    }
+
+    #[test]
+    fn masked_quads() {
+        use crate::material::{AlphaMode, Masked, Mix};
+
+        let r = Ray::new(Vec3::new(0.5, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let quad = |alpha: f32, mode| {
+            Quad::xy(
+                0.0,
+                1.0,
+                0.0,
+                1.0,
+                0.0,
+                Arc::new(Masked::new(material(), alpha, mode)),
+            )
+        };
+        assert!(quad(0.4, AlphaMode::Cutout(0.5))
+            .hit(&r, 0.0, f32::MAX)
+            .is_none());
+        assert!(quad(0.6, AlphaMode::Cutout(0.5))
+            .hit(&r, 0.0, f32::MAX)
+            .is_some());
+        assert!(quad(0.0, AlphaMode::Stochastic)
+            .hit(&r, 0.0, f32::MAX)
+            .is_none());
+        assert!(quad(1.0, AlphaMode::Stochastic)
+            .hit(&r, 0.0, f32::MAX)
+            .is_some());
+
+        // blended with a solid material, as much as it is used
+        let mixed = |factor: f32| {
+            let hole = Arc::new(Masked::new(material(), 0.0, AlphaMode::Cutout(0.5)));
+            let mix = Mix::new(hole, material(), factor);
+            Quad::xy(0.0, 1.0, 0.0, 1.0, 0.0, Arc::new(mix))
+        };
+        assert!(mixed(0.0).hit(&r, 0.0, f32::MAX).is_none());
+        assert!(mixed(1.0).hit(&r, 0.0, f32::MAX).is_some());
+
+        // where it is there, the cut-out red never shades it
+        let red = Arc::new(Masked::new(
+            Arc::new(Lambertian::new(1.0, 0.0, 0.0)),
+            0.0,
+            AlphaMode::Cutout(0.5),
+        ));
+        let blue = Arc::new(Lambertian::new(0.0, 0.0, 1.0));
+        let quad = Quad::xy(0.0, 1.0, 0.0, 1.0, 0.0, Arc::new(Mix::new(red, blue, 0.5)));
+        let (mut hits, mut misses) = (0, 0);
+        for _ in 0..1000 {
+            let rec = match quad.hit(&r, 0.0, f32::MAX) {
+                Some(rec) => rec,
+                None => {
+                    misses += 1;
+                    continue;
+                }
+            };
+            hits += 1;
+            let s = rec.material.scatter(&r, &rec).unwrap();
+            assert_eq!(s.attenuation, Vec3::new(0.0, 0.0, 1.0));
+            let (wo, wi) = (-r.direction, s.scattered.direction.unit_vector());
+            assert_eq!(rec.material.eval(&rec, &wo, &wi).x, 0.0);
+        }
+        assert!(hits > 400 && misses > 400, "{} {}", hits, misses);
+    }
+}
@@ -0,0 +1,155 @@
+use crate::transform::Mat4;
+use crate::vec3::Vec3;
+
+// Unit quaternion `w + v`, representing a rotation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quat {
+    pub w: f32,
+    pub v: Vec3,
+}
+
+impl Quat {
+    pub fn identity() -> Self {
+        Quat {
+            w: 1.0,
+            v: Vec3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    // Quaternion of the rotation in the upper 3x3 block of `m`, which must
+    // be orthonormal with a positive determinant
+    pub fn from_rotation(m: &Mat4) -> Self {
+        let m = &m.m;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        let q = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt();
+            let f = 0.5 / s;
+            Quat {
+                w: 0.5 * s,
+                v: Vec3::new(
+                    (m[2][1] - m[1][2]) * f,
+                    (m[0][2] - m[2][0]) * f,
+                    (m[1][0] - m[0][1]) * f,
+                ),
+            }
+        } else {
+            // start from the largest diagonal element for accuracy
+            let i = if m[1][1] > m[0][0] { 1 } else { 0 };
+            let i = if m[2][2] > m[i][i] { 2 } else { i };
+            let (j, k) = ((i + 1) % 3, (i + 2) % 3);
+            let s = (m[i][i] - m[j][j] - m[k][k] + 1.0).sqrt();
+            let f = 0.5 / s;
+            let mut v = [0.0; 3];
+            v[i] = 0.5 * s;
+            v[j] = (m[j][i] + m[i][j]) * f;
+            v[k] = (m[k][i] + m[i][k]) * f;
+            Quat {
+                w: (m[k][j] - m[j][k]) * f,
+                v: Vec3::new(v[0], v[1], v[2]),
+            }
+        };
+        q.normalized()
+    }
+
+    pub fn to_matrix(&self) -> Mat4 {
+        let (w, x, y, z) = (self.w, self.v.x, self.v.y, self.v.z);
+        let mut m = Mat4::identity();
+        m.m[0][0] = 1.0 - 2.0 * (y * y + z * z);
+        m.m[0][1] = 2.0 * (x * y - z * w);
+        m.m[0][2] = 2.0 * (x * z + y * w);
+        m.m[1][0] = 2.0 * (x * y + z * w);
+        m.m[1][1] = 1.0 - 2.0 * (x * x + z * z);
+        m.m[1][2] = 2.0 * (y * z - x * w);
+        m.m[2][0] = 2.0 * (x * z - y * w);
+        m.m[2][1] = 2.0 * (y * z + x * w);
+        m.m[2][2] = 1.0 - 2.0 * (x * x + y * y);
+        m
+    }
+
+    pub fn dot(&self, other: &Quat) -> f32 {
+        self.w * other.w + self.v.dot(&other.v)
+    }
+
+    pub fn normalized(&self) -> Quat {
+        let len = self.dot(self).sqrt();
+        Quat {
+            w: self.w / len,
+            v: self.v / len,
+        }
+    }
+
+    // Angle in radians of the rotation from `self` to `other`
+    pub fn angle_to(&self, other: &Quat) -> f32 {
+        2.0 * self.dot(other).abs().min(1.0).acos()
+    }
+
+    // Spherical linear interpolation along the shortest arc, at constant
+    // angular speed
+    pub fn slerp(&self, other: &Quat, t: f32) -> Quat {
+        let mut cos = self.dot(other);
+        let mut other = *other;
+        // q and -q are the same rotation
+        if cos < 0.0 {
+            cos = -cos;
+            other = Quat {
+                w: -other.w,
+                v: -other.v,
+            };
+        }
+        let (a, b) = if cos > 0.9995 {
+            // nearly parallel: linear interpolation is accurate
+            (1.0 - t, t)
+        } else {
+            let theta = cos.acos();
+            let sin = theta.sin();
+            (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+        Quat {
+            w: a * self.w + b * other.w,
+            v: a * self.v + b * other.v,
+        }
+        .normalized()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::Transform;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn matrix_round_trip() {
+        for &(axis, degrees) in &[
+            (Vec3::new(0.0, 0.0, 1.0), 30.0),
+            (Vec3::new(1.0, 2.0, 3.0), 170.0),
+            (Vec3::new(1.0, 0.0, 0.0), 180.0),
+            (Vec3::new(0.0, 1.0, 0.0), -90.0),
+        ] {
+            let rotation = Transform::rotate(axis, degrees);
+            let q = Quat::from_rotation(&rotation.matrix);
+            let p = Vec3::new(0.3, -1.0, 2.0);
+            assert_close(q.to_matrix().transform_point(&p), rotation.point(&p));
+            assert!((q.angle_to(&Quat::identity()) - degrees.abs().to_radians()).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn slerp_at_constant_speed() {
+        let axis = Vec3::new(0.0, 0.0, 1.0);
+        let a = Quat::from_rotation(&Transform::rotate(axis, 0.0).matrix);
+        let b = Quat::from_rotation(&Transform::rotate(axis, 120.0).matrix);
+        let x = Vec3::new(1.0, 0.0, 0.0);
+        for &t in &[0.0, 0.25, 0.5, 1.0] {
+            let expected = Transform::rotate(axis, 120.0 * t).point(&x);
+            assert_close(a.slerp(&b, t).to_matrix().transform_point(&x), expected);
+        }
+        // the short way around from 0 to 270 degrees is -90 degrees
+        let c = Quat::from_rotation(&Transform::rotate(axis, 270.0).matrix);
+        let half = a.slerp(&c, 0.5).to_matrix().transform_point(&x);
+        assert_close(half, Transform::rotate(axis, -45.0).point(&x));
+    }
+}
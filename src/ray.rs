@@ -4,11 +4,26 @@ use crate::vec3::Vec3;
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
+    // Instant of the ray within the shutter interval, for motion blur
+    pub time: f32,
+    // Hero wavelength in nanometers in spectral rendering, for materials
+    // that depend on it
+    pub wavelength: Option<f32>,
 }
 
 impl Ray {
+    // Ray at time 0
     pub fn new(origin: Vec3, direction: Vec3) -> Self {
-        Ray { origin, direction }
+        Ray::with_time(origin, direction, 0.0)
+    }
+
+    pub fn with_time(origin: Vec3, direction: Vec3, time: f32) -> Self {
+        Ray {
+            origin,
+            direction,
+            time,
+            wavelength: None,
+        }
     }
 
     pub fn point_at_parameter(&self, t: f32) -> Vec3 {
@@ -26,6 +41,8 @@ mod tests {
         let ray = Ray {
             origin: Vec3::new(0.0, 0.0, 0.0),
             direction: Vec3::new(1.0, 2.0, 3.0),
+            time: 0.0,
+            wavelength: None,
         };
         let t: f32 = 2.0;
         assert_eq!(ray.point_at_parameter(t), Vec3::new(2.0, 4.0, 6.0));
@@ -0,0 +1,645 @@
+use crate::camera::Camera;
+use crate::crop::CropWindow;
+use crate::error::{Error, Result};
+use crate::hitable::{HitRecord, Hitable};
+use crate::image::Image;
+use crate::material::{HenyeyGreenstein, Material, ScatterRecord};
+use crate::medium_stack::{Boundary, Flight, MediumStack};
+use crate::random;
+use crate::ray::Ray;
+use crate::spectrum::{self, SampledSpectrum, SampledWavelengths};
+use crate::stats::{self, RayCounts, RenderStats};
+use crate::vec3::Vec3;
+
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+// Most scattering events in a row in a medium, to bound the time a path can
+// take in one that does not absorb
+const MAX_WALK_STEPS: u32 = 100_000;
+
+// Light coming from directions where a ray leaves the scene
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Background {
+    // White at the horizon to light blue at the zenith
+    Sky,
+    Uniform(Vec3),
+}
+
+impl Background {
+    pub fn color(&self, direction: &Vec3) -> Vec3 {
+        match self {
+            Background::Sky => {
+                let unit_direction: Vec3 = direction.unit_vector();
+                let t: f32 = 0.5 * (unit_direction.y + 1.0);
+                (1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0)
+            }
+            Background::Uniform(c) => *c,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    // number of samples inside each pixel
+    pub samples: u32,
+    // maximum number of bounces of a path
+    pub max_depth: u32,
+    // Render only this region of the frame. The camera projection stays
+    // the one of the full `width` x `height` frame.
+    pub crop: Option<CropWindow>,
+    // Seed of the random numbers. With a seed, rendering the same scene gives
+    // the same image whatever the number of threads.
+    pub seed: Option<u64>,
+    pub background: Background,
+    // Trace wavelengths instead of RGB, for dispersion. Colors of materials
+    // and of the background are turned into spectra.
+    pub spectral: bool,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            width: 400,
+            height: 300,
+            samples: 10,
+            max_depth: 50,
+            crop: None,
+            seed: None,
+            background: Background::Sky,
+            spectral: false,
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn aspect(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.width == 0 || self.height == 0 {
+            return Err(Error::InvalidSettings(format!(
+                "image size is {}x{}",
+                self.width, self.height
+            )));
+        }
+        if self.samples == 0 {
+            return Err(Error::InvalidSettings(
+                "at least one sample per pixel is needed".to_string(),
+            ));
+        }
+        if self.window().is_empty() {
+            return Err(Error::InvalidSettings(format!(
+                "crop window {:?} does not overlap the {}x{} image",
+                self.crop, self.width, self.height
+            )));
+        }
+        Ok(())
+    }
+
+    // Region actually rendered, clamped to the frame
+    pub fn window(&self) -> CropWindow {
+        self.crop
+            .unwrap_or_else(|| CropWindow::full(self.width, self.height))
+            .clamp(self.width, self.height)
+    }
+}
+
+// Snapshot of the progress of a render, passed to the progress callback
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub pixels_done: u64,
+    pub pixels_total: u64,
+    pub rays: u64,
+}
+
+pub struct RenderOutput {
+    // Rendered region, of the size of the crop window
+    pub image: Image,
+    pub stats: RenderStats,
+    // The render was stopped by the cancel callback; pixels not rendered are black.
+    pub cancelled: bool,
+}
+
+type ProgressCallback<'a> = Box<dyn Fn(Progress) + Send + Sync + 'a>;
+type CancelCallback<'a> = Box<dyn Fn() -> bool + Send + Sync + 'a>;
+
+// Path tracer rendering a scene seen from a camera into an `Image`.
+//
+//     let renderer = Renderer::new(RenderSettings::default())
+//         .on_progress(|p| println!("{}/{}", p.pixels_done, p.pixels_total));
+//     let output = renderer.render(&world, &camera);
+//
+// The callbacks are called from the rayon worker threads.
+pub struct Renderer<'a> {
+    pub settings: RenderSettings,
+    progress: Option<ProgressCallback<'a>>,
+    cancel: Option<CancelCallback<'a>>,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(settings: RenderSettings) -> Self {
+        Renderer {
+            settings,
+            progress: None,
+            cancel: None,
+        }
+    }
+
+    // Call `f` each time a pixel is done
+    pub fn on_progress<F>(mut self, f: F) -> Self
+    where
+        F: Fn(Progress) + Send + Sync + 'a,
+    {
+        self.progress = Some(Box::new(f));
+        self
+    }
+
+    // Stop rendering as soon as `f` returns true. It is polled before each pixel.
+    pub fn cancel_when<F>(mut self, f: F) -> Self
+    where
+        F: Fn() -> bool + Send + Sync + 'a,
+    {
+        self.cancel = Some(Box::new(f));
+        self
+    }
+
+    pub fn render(&self, world: &dyn Hitable, cam: &Camera) -> Result<RenderOutput> {
+        self.settings.validate()?;
+        cam.validate()?;
+
+        let RenderSettings {
+            width: nx,
+            height: ny,
+            samples: ns,
+            seed,
+            ..
+        } = self.settings;
+        let window = self.settings.window();
+        let pixels_total = window.width() as u64 * window.height() as u64;
+        let pixels_done = AtomicU64::new(0);
+        let rays_done = AtomicU64::new(0);
+        let start = Instant::now();
+
+        // Only the pixels inside the crop window are traced, but rays are still
+        // generated over the full frame so the projection does not change.
+        let (pixels, counts): (Vec<Option<Vec3>>, Vec<RayCounts>) = window
+            .pixels()
+            .par_iter()
+            .cloned()
+            .map(|(x, y)| {
+                if self.cancelled() {
+                    return (None, RayCounts::default());
+                }
+                let i = x;
+                let j = ny - 1 - y;
+                let (mut col, counts): (Vec3, RayCounts) = (0..ns)
+                    .into_par_iter()
+                    .map(|s| {
+                        if let Some(seed) = seed {
+                            random::seed(random::hash_seed(&[seed, (j * nx + i) as u64, s as u64]));
+                        }
+                        let tests = stats::intersection_tests();
+                        let mut counts = RayCounts {
+                            primary_rays: 1,
+                            ..Default::default()
+                        };
+                        let u = (i as f32 + random::next_f32()) / nx as f32;
+                        let v = (j as f32 + random::next_f32()) / ny as f32;
+                        let r: Ray = cam.get_ray(u, v);
+                        let col = if self.settings.spectral {
+                            self.spectral_color(r, world, &mut counts)
+                        } else {
+                            self.color(&r, world, 0, &mut MediumStack::new(), &mut counts)
+                        };
+                        counts.intersection_tests = stats::intersection_tests() - tests;
+                        (col, counts)
+                    })
+                    .reduce(
+                        || (Vec3::new(0.0, 0.0, 0.0), RayCounts::default()),
+                        |a, b| (a.0 + b.0, a.1 + b.1),
+                    );
+                col /= ns as f32;
+
+                let done = pixels_done.fetch_add(1, Ordering::Relaxed) + 1;
+                let rays = rays_done.fetch_add(counts.total_rays(), Ordering::Relaxed)
+                    + counts.total_rays();
+                if let Some(progress) = &self.progress {
+                    progress(Progress {
+                        pixels_done: done,
+                        pixels_total,
+                        rays,
+                    });
+                }
+                (Some(col), counts)
+            })
+            .unzip();
+
+        let cancelled = pixels.iter().any(Option::is_none);
+        let image = Image {
+            width: window.width(),
+            height: window.height(),
+            pixels: pixels
+                .into_iter()
+                .map(|pix| pix.unwrap_or_else(|| Vec3::new(0.0, 0.0, 0.0)))
+                .collect(),
+        };
+        let stats = RenderStats {
+            rays: counts.into_iter().fold(RayCounts::default(), |a, b| a + b),
+            render: start.elapsed(),
+            ..Default::default()
+        };
+        Ok(RenderOutput {
+            image,
+            stats,
+            cancelled,
+        })
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|f| f())
+    }
+
+    // Where the surface hit by `r` sends it, keeping track of the media the
+    // path goes through
+    fn scatter(&self, r: &Ray, rec: &HitRecord, media: &mut MediumStack) -> Option<ScatterRecord> {
+        let interior = match rec.material.interior() {
+            None => return rec.material.scatter(r, rec),
+            Some(interior) => interior,
+        };
+        let entering = rec.front_face;
+        match media.boundary(rec.material, &interior, entering, r.wavelength) {
+            // carry on as if the surface was not there
+            Boundary::Skip => {
+                media.cross(rec.material, interior, entering);
+                Some(ScatterRecord {
+                    attenuation: Vec3::new(1.0, 1.0, 1.0),
+                    scattered: Ray {
+                        origin: rec.p,
+                        ..*r
+                    },
+                })
+            }
+            Boundary::Refract { n_from, n_to } => {
+                let scatter_record = rec.material.scatter_between(r, rec, n_from, n_to);
+                if let Some(s) = &scatter_record {
+                    let through =
+                        s.scattered.direction.dot(&rec.normal) * r.direction.dot(&rec.normal) > 0.0;
+                    if through {
+                        media.cross(rec.material, interior, entering);
+                    }
+                }
+                scatter_record
+            }
+        }
+    }
+
+    // Ray leaving the point at `distance` along `r` where the medium the path
+    // is in scatters it
+    fn scatter_in_medium(r: &Ray, distance: f32, anisotropy: f32) -> Ray {
+        let phase = HenyeyGreenstein::new(Vec3::new(1.0, 1.0, 1.0), anisotropy);
+        let direction = r.direction.unit_vector();
+        let rec = HitRecord {
+            t: distance / r.direction.length(),
+            p: r.origin + distance * direction,
+            normal: -direction,
+            front_face: true,
+            u: 0.0,
+            v: 0.0,
+            dpdu: Vec3::new(0.0, 0.0, 0.0),
+            dpdv: Vec3::new(0.0, 0.0, 0.0),
+            material: &phase,
+        };
+        let scattered = phase.scatter(r, &rec).unwrap().scattered;
+        Ray {
+            wavelength: r.wavelength,
+            ..scattered
+        }
+    }
+
+    // Follow `r` through the medium it is in to the next surface, scattering
+    // on the way in scattering media. Returns the ray reaching the surface,
+    // the surface, `None` if the ray leaves the scene, and the weight of the
+    // light along the walk; `None` when the walk is cut short. The steps of a
+    // walk do not count against `max_depth`: dense media such as skin take
+    // thousands of them. Russian roulette ends the walks carrying little light
+    // instead.
+    fn walk<'w>(
+        &self,
+        r: &Ray,
+        world: &'w dyn Hitable,
+        media: &MediumStack,
+        counts: &mut RayCounts,
+    ) -> Option<(Ray, Option<HitRecord<'w>>, Vec3)> {
+        let mut r = *r;
+        let mut weight = Vec3::new(1.0, 1.0, 1.0);
+        for _ in 0..MAX_WALK_STEPS {
+            let rec = match world.hit(&r, 0.001, f32::MAX) {
+                Some(rec) => rec,
+                // Open surfaces may let a path out of a medium: it is not
+                // attenuated any more.
+                None => return Some((r, None, weight)),
+            };
+            if media.is_empty() {
+                return Some((r, Some(rec), weight));
+            }
+            match media.flight(rec.t * r.direction.length()) {
+                Flight::Through { weight: w } => return Some((r, Some(rec), weight * w)),
+                Flight::Scattered {
+                    distance,
+                    weight: w,
+                    anisotropy,
+                } => {
+                    weight = weight * w;
+                    let survival = weight.x.max(weight.y).max(weight.z).min(1.0);
+                    if survival < 1.0 {
+                        if random::next_f32() >= survival {
+                            return None;
+                        }
+                        weight /= survival;
+                    }
+                    r = Self::scatter_in_medium(&r, distance, anisotropy);
+                    counts.secondary_rays += 1;
+                }
+            }
+        }
+        None
+    }
+
+    // Radiance carried back along the ray `r`, which starts inside `media`
+    fn color(
+        &self,
+        r: &Ray,
+        world: &dyn Hitable,
+        depth: u32,
+        media: &mut MediumStack,
+        counts: &mut RayCounts,
+    ) -> Vec3 {
+        // absorbed or scattered on the way by the medium the ray is in
+        let (r, rec, transmittance) = match self.walk(r, world, media, counts) {
+            Some(walk) => walk,
+            None => return Vec3::new(0.0, 0.0, 0.0),
+        };
+        let rec = match rec {
+            Some(rec) => rec,
+            None => return transmittance * self.settings.background.color(&r.direction),
+        };
+        let emitted = transmittance * rec.material.emitted(&rec);
+        if let Some(scatter_record) = self.scatter(&r, &rec, media) {
+            if depth < self.settings.max_depth {
+                let attenuation: Vec3 = scatter_record.attenuation;
+                let scattered: Ray = scatter_record.scattered;
+                counts.secondary_rays += 1;
+                emitted
+                    + transmittance
+                        * attenuation
+                        * self.color(&scattered, world, depth + 1, media, counts)
+            } else {
+                emitted
+            }
+        } else {
+            emitted
+        }
+    }
+
+    // Linear sRGB color of a path from the camera ray `r` carrying
+    // wavelengths drawn at random
+    fn spectral_color(&self, r: Ray, world: &dyn Hitable, counts: &mut RayCounts) -> Vec3 {
+        let mut wavelengths = SampledWavelengths::sample_uniform(random::next_f32());
+        let r = Ray {
+            wavelength: Some(wavelengths.hero()),
+            ..r
+        };
+        let mut media = MediumStack::new();
+        let radiance = self.spectral_radiance(&r, world, 0, &mut media, &mut wavelengths, counts);
+        spectrum::xyz_to_srgb(&spectrum::to_xyz(&radiance, &wavelengths))
+    }
+
+    // Like `color`, at the wavelengths of the path. Materials only follow the
+    // hero wavelength of the ray, so after a dispersive one the others are
+    // dropped.
+    fn spectral_radiance(
+        &self,
+        r: &Ray,
+        world: &dyn Hitable,
+        depth: u32,
+        media: &mut MediumStack,
+        wavelengths: &mut SampledWavelengths,
+        counts: &mut RayCounts,
+    ) -> SampledSpectrum {
+        let (r, rec, weight) = match self.walk(r, world, media, counts) {
+            Some(walk) => walk,
+            None => return SampledSpectrum::splat(0.0),
+        };
+        let transmittance = if media.is_empty() {
+            SampledSpectrum::splat(1.0)
+        } else {
+            spectrum::rgb_to_spectrum(&weight, wavelengths)
+        };
+        let rec = match rec {
+            Some(rec) => rec,
+            None => {
+                let background = self.settings.background.color(&r.direction);
+                return transmittance * spectrum::rgb_to_spectrum(&background, wavelengths);
+            }
+        };
+        let emitted =
+            transmittance * spectrum::rgb_to_spectrum(&rec.material.emitted(&rec), wavelengths);
+        match self.scatter(&r, &rec, media) {
+            Some(scatter_record) if depth < self.settings.max_depth => {
+                if rec.material.is_dispersive() {
+                    wavelengths.terminate_secondary();
+                }
+                let attenuation =
+                    spectrum::rgb_to_spectrum(&scatter_record.attenuation, wavelengths);
+                let scattered = Ray {
+                    wavelength: r.wavelength,
+                    ..scatter_record.scattered
+                };
+                counts.secondary_rays += 1;
+                emitted
+                    + transmittance
+                        * attenuation
+                        * self.spectral_radiance(
+                            &scattered,
+                            world,
+                            depth + 1,
+                            media,
+                            wavelengths,
+                            counts,
+                        )
+            }
+            _ => emitted,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hitable_list::HitableList;
+
+    fn camera(settings: &RenderSettings) -> Camera {
+        let lookfrom = Vec3::new(0.0, 0.0, 1.0);
+        let lookat = Vec3::new(0.0, 0.0, 0.0);
+        let vup = Vec3::new(0.0, 1.0, 0.0);
+        Camera::new(lookfrom, lookat, vup, 90.0, settings.aspect(), 0.0, 1.0)
+    }
+
+    #[test]
+    fn render_crop_of_empty_scene() {
+        let settings = RenderSettings {
+            width: 8,
+            height: 6,
+            samples: 2,
+            crop: Some(CropWindow::new(2, 1, 5, 3)),
+            ..Default::default()
+        };
+        let world = HitableList { hitables: vec![] };
+        let output = Renderer::new(settings)
+            .render(&world, &camera(&settings))
+            .unwrap();
+
+        assert!(!output.cancelled);
+        assert_eq!((output.image.width, output.image.height), (3, 2));
+        assert_eq!(output.stats.rays.primary_rays, 12);
+        assert_eq!(output.stats.rays.secondary_rays, 0);
+        // the sky gets bluer towards the top
+        assert!(output.image.get(0, 0).x < output.image.get(0, 1).x);
+    }
+
+    #[test]
+    fn cancelled_render_stops_early() {
+        let settings = RenderSettings {
+            width: 8,
+            height: 6,
+            samples: 2,
+            ..Default::default()
+        };
+        let world = HitableList { hitables: vec![] };
+        let output = Renderer::new(settings)
+            .cancel_when(|| true)
+            .render(&world, &camera(&settings))
+            .unwrap();
+
+        assert!(output.cancelled);
+        assert_eq!(output.stats.rays.primary_rays, 0);
+        assert_eq!(output.image, Image::new(8, 6));
+    }
+
+    // Color of the center of a render looking at `world` along -z from
+    // z = 3, through a narrow field of view
+    fn center_color(world: &HitableList) -> Vec3 {
+        let settings = RenderSettings {
+            width: 1,
+            height: 1,
+            samples: 16,
+            seed: Some(3),
+            background: Background::Uniform(Vec3::new(1.0, 1.0, 1.0)),
+            ..Default::default()
+        };
+        let cam = Camera::new(
+            Vec3::new(0.0, 0.0, 3.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.01,
+            1.0,
+            0.0,
+            3.0,
+        );
+        Renderer::new(settings)
+            .render(world, &cam)
+            .unwrap()
+            .image
+            .get(0, 0)
+    }
+
+    #[test]
+    fn absorbing_interiors() {
+        use crate::material::{Dielectric, Ior};
+        use crate::sphere::Sphere;
+        use std::sync::Arc;
+
+        // index 1: no refraction nor reflection at normal incidence
+        let medium = |absorption, priority| {
+            Arc::new(Dielectric {
+                ior: Ior::Constant(1.0),
+                absorption,
+                priority,
+                film: None,
+            })
+        };
+        let sphere =
+            |radius, material| Arc::new(Sphere::new(Vec3::new(0.0, 0.0, 0.0), radius, material));
+        let absorption = Vec3::new(0.5, 0.25, 0.0);
+        let clear = Vec3::new(0.0, 0.0, 0.0);
+
+        // Beer-Lambert over the diameter
+        let world = HitableList {
+            hitables: vec![sphere(1.0, medium(absorption, 0))],
+        };
+        let expected = Vec3::new((-1.0f32).exp(), (-0.5f32).exp(), 1.0);
+        assert!((center_color(&world) - expected).length() < 1e-4);
+
+        // a clear medium of higher priority displaces the absorbing one
+        let world = HitableList {
+            hitables: vec![
+                sphere(1.0, medium(absorption, 0)),
+                sphere(0.5, medium(clear, 1)),
+            ],
+        };
+        let expected = Vec3::new((-0.5f32).exp(), (-0.25f32).exp(), 1.0);
+        assert!((center_color(&world) - expected).length() < 1e-4);
+
+        // and is ignored with a lower priority
+        let world = HitableList {
+            hitables: vec![
+                sphere(1.0, medium(absorption, 2)),
+                sphere(0.5, medium(clear, 1)),
+            ],
+        };
+        let expected = Vec3::new((-1.0f32).exp(), (-0.5f32).exp(), 1.0);
+        assert!((center_color(&world) - expected).length() < 1e-4);
+    }
+
+    #[test]
+    fn emissive_surfaces() {
+        use crate::principled::Principled;
+        use crate::sphere::Sphere;
+        use std::sync::Arc;
+
+        // black and without reflection at normal incidence: only the emission
+        let emission = Vec3::new(2.0, 1.0, 0.5);
+        let lamp = Principled::new(Vec3::new(0.0, 0.0, 0.0))
+            .with_specular(0.0)
+            .with_emission(emission);
+        let world = HitableList {
+            hitables: vec![Arc::new(Sphere::new(
+                Vec3::new(0.0, 0.0, 0.0),
+                1.0,
+                Arc::new(lamp),
+            ))],
+        };
+        assert!((center_color(&world) - emission).length() < 1e-4);
+    }
+
+    #[test]
+    fn reject_invalid_settings() {
+        let settings = RenderSettings {
+            width: 8,
+            height: 6,
+            crop: Some(CropWindow::new(10, 0, 20, 6)),
+            ..Default::default()
+        };
+        let world = HitableList { hitables: vec![] };
+        let cam = camera(&settings);
+        match Renderer::new(settings).render(&world, &cam) {
+            Err(Error::InvalidSettings(_)) => {}
+            _ => panic!("empty crop window must be rejected"),
+        }
+    }
+}
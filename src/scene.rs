@@ -1,3 +1,4 @@
+use crate::camera::Camera;
 use crate::hitable::*;
 use crate::hitable_list::HitableList;
 use crate::material::*;
@@ -5,11 +6,31 @@ use crate::sphere::Sphere;
 use crate::vec3::Vec3;
 
 use rand::prelude::*;
+use rand::rngs::StdRng;
 use std::sync::Arc;
 
 pub fn random_scene() -> HitableList {
-    let mut hitables: Vec<Arc<Hitable>> = vec![];
-    let mut rng = rand::thread_rng();
+    random_scene_from(&mut rand::thread_rng())
+}
+
+// Same scene for the same seed
+pub fn random_scene_with_seed(seed: u64) -> HitableList {
+    random_scene_from(&mut StdRng::seed_from_u64(seed))
+}
+
+// Camera looking at the three big spheres of `random_scene`
+pub fn random_scene_camera(aspect: f32) -> Camera {
+    let lookfrom: Vec3 = Vec3::new(13.0, 2.5, 3.0);
+    let lookat: Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    let vup: Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    let vfov: f32 = 20.0;
+    let aperture: f32 = 0.1;
+    let dist_to_focus: f32 = (lookfrom - lookat).length();
+    Camera::new(lookfrom, lookat, vup, vfov, aspect, aperture, dist_to_focus)
+}
+
+fn random_scene_from<R: Rng>(rng: &mut R) -> HitableList {
+    let mut hitables: Vec<Arc<dyn Hitable>> = vec![];
 
     // earth
     hitables.push(Arc::new(Sphere {
@@ -0,0 +1,259 @@
+use crate::vec3::Vec3;
+
+use std::ops::{Add, Mul};
+
+// Range of the sampled wavelengths, in nanometers
+pub const LAMBDA_MIN: f32 = 360.0;
+pub const LAMBDA_MAX: f32 = 830.0;
+
+// Wavelengths carried by a path
+pub const SAMPLES: usize = 4;
+
+// Integral of the CIE y color matching function over the sampled range
+pub const CIE_Y_INTEGRAL: f32 = 106.922_07;
+
+// Values of a spectrum at the wavelengths of a path
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SampledSpectrum(pub [f32; SAMPLES]);
+
+impl SampledSpectrum {
+    pub fn splat(value: f32) -> Self {
+        SampledSpectrum([value; SAMPLES])
+    }
+}
+
+impl Add for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn add(self, other: SampledSpectrum) -> SampledSpectrum {
+        let mut sum = self;
+        for (s, o) in sum.0.iter_mut().zip(other.0.iter()) {
+            *s += o;
+        }
+        sum
+    }
+}
+
+impl Mul for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn mul(self, other: SampledSpectrum) -> SampledSpectrum {
+        let mut product = self;
+        for (s, o) in product.0.iter_mut().zip(other.0.iter()) {
+            *s *= o;
+        }
+        product
+    }
+}
+
+// Wavelengths of a path with their probability densities. The first one is
+// the hero wavelength, which decides the direction of dispersed rays; the
+// others are spread evenly over the range, so that one path estimates several
+// wavelengths at once.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SampledWavelengths {
+    pub lambda: [f32; SAMPLES],
+    pub pdf: [f32; SAMPLES],
+}
+
+impl SampledWavelengths {
+    // Hero wavelength at `u` in [0, 1) of the range
+    pub fn sample_uniform(u: f32) -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let mut lambda = [0.0; SAMPLES];
+        for (i, l) in lambda.iter_mut().enumerate() {
+            let offset = (u + i as f32 / SAMPLES as f32).fract();
+            *l = LAMBDA_MIN + offset * range;
+        }
+        SampledWavelengths {
+            lambda,
+            pdf: [1.0 / range; SAMPLES],
+        }
+    }
+
+    pub fn hero(&self) -> f32 {
+        self.lambda[0]
+    }
+
+    // Keep only the hero wavelength, after the path went through a surface
+    // sending each wavelength its own way
+    pub fn terminate_secondary(&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+        for pdf in &mut self.pdf[1..] {
+            *pdf = 0.0;
+        }
+        self.pdf[0] /= SAMPLES as f32;
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.pdf[1..].iter().all(|&pdf| pdf == 0.0)
+    }
+}
+
+// Piecewise Gaussian of Wyman et al., "Simple Analytic Approximations to the
+// CIE XYZ Color Matching Functions"
+fn lobe(lambda: f32, mean: f32, sigma_below: f32, sigma_above: f32) -> f32 {
+    let sigma = if lambda < mean {
+        sigma_below
+    } else {
+        sigma_above
+    };
+    let x = (lambda - mean) / sigma;
+    (-0.5 * x * x).exp()
+}
+
+// CIE 1931 color matching functions at `lambda` in nanometers
+pub fn cie_xyz(lambda: f32) -> Vec3 {
+    Vec3::new(
+        1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+            - 0.065 * lobe(lambda, 501.1, 20.4, 26.2),
+        0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1),
+        1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8),
+    )
+}
+
+// XYZ color of `spectrum` sampled at `wavelengths`, with Y = 1 for the
+// constant spectrum 1
+pub fn to_xyz(spectrum: &SampledSpectrum, wavelengths: &SampledWavelengths) -> Vec3 {
+    let mut xyz = Vec3::new(0.0, 0.0, 0.0);
+    for i in 0..SAMPLES {
+        if wavelengths.pdf[i] > 0.0 {
+            xyz += cie_xyz(wavelengths.lambda[i]) * (spectrum.0[i] / wavelengths.pdf[i]);
+        }
+    }
+    xyz / (SAMPLES as f32 * CIE_Y_INTEGRAL)
+}
+
+// Linear sRGB from XYZ. The white point is adapted (Bradford) to the one of
+// the constant spectrum, which `rgb_to_spectrum` gives for white: white
+// surfaces under a white sky stay white.
+pub fn xyz_to_srgb(xyz: &Vec3) -> Vec3 {
+    Vec3::new(
+        3.147_809_5 * xyz.x - 1.662_846_3 * xyz.y - 0.480_574_4 * xyz.z,
+        -0.994_747_4 * xyz.x + 1.953_570_9 * xyz.y + 0.039_740_2 * xyz.z,
+        0.063_515_5 * xyz.x - 0.214_510_9 * xyz.y + 1.151_595_2 * xyz.z,
+    )
+}
+
+// Smits, "An RGB to Spectrum Conversion for Reflectances": spectra of the
+// primaries and their complements in 10 bins over 380..720 nm
+const SMITS_MIN: f32 = 380.0;
+const SMITS_MAX: f32 = 720.0;
+const SMITS_BINS: usize = 10;
+const SMITS_CYAN: [f32; SMITS_BINS] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0, 0.0, 0.0,
+];
+const SMITS_MAGENTA: [f32; SMITS_BINS] = [
+    1.0, 1.0, 0.9685, 0.2229, 0.0, 0.0458, 0.8369, 1.0, 1.0, 0.9959,
+];
+const SMITS_YELLOW: [f32; SMITS_BINS] = [
+    0.0001, 0.0, 0.1088, 0.6651, 1.0, 1.0, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f32; SMITS_BINS] = [
+    0.1012, 0.0515, 0.0, 0.0, 0.0, 0.0, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f32; SMITS_BINS] = [
+    0.0, 0.0, 0.0273, 0.7937, 1.0, 0.9418, 0.1719, 0.0, 0.0, 0.0025,
+];
+const SMITS_BLUE: [f32; SMITS_BINS] = [
+    1.0, 1.0, 0.8916, 0.3323, 0.0, 0.0, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+// Value of a Smits table at `lambda`, interpolated between the bin centers
+fn smits(table: &[f32; SMITS_BINS], lambda: f32) -> f32 {
+    let width = (SMITS_MAX - SMITS_MIN) / SMITS_BINS as f32;
+    let x = ((lambda - SMITS_MIN) / width - 0.5).clamp(0.0, (SMITS_BINS - 1) as f32);
+    let i = (x as usize).min(SMITS_BINS - 2);
+    let f = x - i as f32;
+    (1.0 - f) * table[i] + f * table[i + 1]
+}
+
+// Smooth spectrum of the linear sRGB color `rgb` at `lambda`: the white part
+// is constant, the rest made of the spectra of one primary and one
+// complementary color.
+pub fn rgb_to_spectrum_at(rgb: &Vec3, lambda: f32) -> f32 {
+    let (r, g, b) = (rgb.x, rgb.y, rgb.z);
+    let s = |table: &[f32; SMITS_BINS]| smits(table, lambda);
+    if r <= g && r <= b {
+        if g <= b {
+            r + (g - r) * s(&SMITS_CYAN) + (b - g) * s(&SMITS_BLUE)
+        } else {
+            r + (b - r) * s(&SMITS_CYAN) + (g - b) * s(&SMITS_GREEN)
+        }
+    } else if g <= r && g <= b {
+        if r <= b {
+            g + (r - g) * s(&SMITS_MAGENTA) + (b - r) * s(&SMITS_BLUE)
+        } else {
+            g + (b - g) * s(&SMITS_MAGENTA) + (r - b) * s(&SMITS_RED)
+        }
+    } else if r <= g {
+        b + (r - b) * s(&SMITS_YELLOW) + (g - r) * s(&SMITS_GREEN)
+    } else {
+        b + (g - b) * s(&SMITS_YELLOW) + (r - g) * s(&SMITS_RED)
+    }
+}
+
+pub fn rgb_to_spectrum(rgb: &Vec3, wavelengths: &SampledWavelengths) -> SampledSpectrum {
+    let mut spectrum = [0.0; SAMPLES];
+    for (s, &lambda) in spectrum.iter_mut().zip(wavelengths.lambda.iter()) {
+        *s = rgb_to_spectrum_at(rgb, lambda);
+    }
+    SampledSpectrum(spectrum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // sRGB of `rgb` turned into a spectrum, integrated over `n` wavelengths
+    fn round_trip(rgb: Vec3, n: usize) -> Vec3 {
+        let mut sum = Vec3::new(0.0, 0.0, 0.0);
+        for i in 0..n {
+            let wavelengths = SampledWavelengths::sample_uniform((i as f32 + 0.5) / n as f32);
+            let spectrum = rgb_to_spectrum(&rgb, &wavelengths);
+            sum += xyz_to_srgb(&to_xyz(&spectrum, &wavelengths));
+        }
+        sum / n as f32
+    }
+
+    #[test]
+    fn white_and_primaries() {
+        for lambda in [360.0, 450.0, 555.0, 700.0, 830.0] {
+            let white = rgb_to_spectrum_at(&Vec3::new(0.5, 0.5, 0.5), lambda);
+            assert!((white - 0.5).abs() < 1e-6);
+        }
+        assert!(
+            (round_trip(Vec3::new(1.0, 1.0, 1.0), 1000) - Vec3::new(1.0, 1.0, 1.0)).length() < 1e-3
+        );
+
+        // Smits spectra are smooth, so saturated colors come back desaturated
+        let red = round_trip(Vec3::new(0.8, 0.1, 0.1), 1000);
+        assert!(red.x > 0.5 && red.y < 0.25 && red.z < 0.25, "{:?}", red);
+        let blue = round_trip(Vec3::new(0.1, 0.2, 0.9), 1000);
+        assert!(
+            blue.z > 0.6 && blue.x < blue.y && blue.y < blue.z,
+            "{:?}",
+            blue
+        );
+    }
+
+    #[test]
+    fn hero_wavelengths() {
+        let mut wavelengths = SampledWavelengths::sample_uniform(0.9);
+        assert!((wavelengths.hero() - (LAMBDA_MIN + 0.9 * 470.0)).abs() < 1e-3);
+        assert!((wavelengths.lambda[1] - (LAMBDA_MIN + 0.15 * 470.0)).abs() < 1e-3);
+        assert!(!wavelengths.secondary_terminated());
+
+        // the hero carries the weight of the others
+        let spectrum = SampledSpectrum::splat(1.0);
+        let before = to_xyz(&spectrum, &wavelengths);
+        wavelengths.terminate_secondary();
+        wavelengths.terminate_secondary();
+        assert!(wavelengths.secondary_terminated());
+        let hero = to_xyz(&spectrum, &wavelengths);
+        assert!((hero.y - cie_xyz(wavelengths.hero()).y * 470.0 / CIE_Y_INTEGRAL).abs() < 1e-4);
+        assert!(before != hero);
+    }
+}
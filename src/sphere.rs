@@ -1,20 +1,22 @@
+use crate::aabb::Aabb;
 use crate::hitable::{HitRecord, Hitable};
 use crate::material::Material;
 use crate::ray::Ray;
 use crate::vec3::Vec3;
 
 use std::borrow::Borrow;
+use std::f32::consts::PI;
 use std::sync::Arc;
 
 // #[derive(Debug, Clone, Copy)]
 pub struct Sphere {
     pub center: Vec3,
     pub radius: f32,
-    pub material: Arc<Material>,
+    pub material: Arc<dyn Material>,
 }
 
 impl Sphere {
-    pub fn new(center: Vec3, radius: f32, material: Arc<Material>) -> Self {
+    pub fn new(center: Vec3, radius: f32, material: Arc<dyn Material>) -> Self {
         Sphere {
             center,
             radius,
@@ -24,37 +26,165 @@ impl Sphere {
 }
 
 impl Hitable for Sphere {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let oc: Vec3 = r.origin - self.center;
-        let a: f32 = r.direction.dot(&r.direction);
-        let b: f32 = oc.dot(&r.direction);
-        let c: f32 = oc.dot(&oc) - self.radius * self.radius;
-        let discriminant: f32 = b * b - a * c;
-
-        if discriminant > 0. {
-            // ray crosses the sphere at least once
-            let temp1: f32 = (-b - discriminant.sqrt()) / a;
-            let temp2: f32 = (-b + discriminant.sqrt()) / a;
-            let b1: bool = t_max > temp1 && temp1 > t_min;
-            let b2: bool = t_max > temp2 && temp2 > t_min;
-
-            if discriminant > 0. && (b1 || b2) {
-                // temp1の方がスクリーンに近い
-                let t: f32 = if b1 { temp1 } else { temp2 };
-                let p: Vec3 = r.point_at_parameter(t);
-                let normal: Vec3 = (p - self.center) / self.radius;
-                Some(HitRecord {
-                    t,
-                    p,
-                    normal,
-                    material: self.material.borrow(),
-                })
-            } else {
-                None
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        hit_sphere(
+            self.center,
+            self.radius,
+            self.material.borrow(),
+            r,
+            t_min,
+            t_max,
+        )
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32) -> Option<Aabb> {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(self.center - r, self.center + r))
+    }
+}
+
+// Intersection with the sphere of `center` and `radius`, shared with
+// `MovingSphere`. A negative radius turns the surface inside out: the
+// geometric normals point inwards.
+pub(crate) fn hit_sphere<'a>(
+    center: Vec3,
+    radius: f32,
+    material: &'a dyn Material,
+    r: &Ray,
+    t_min: f32,
+    t_max: f32,
+) -> Option<HitRecord<'a>> {
+    let oc: Vec3 = r.origin - center;
+    let a: f32 = r.direction.dot(&r.direction);
+    let b: f32 = oc.dot(&r.direction);
+    let c: f32 = oc.dot(&oc) - radius * radius;
+    let discriminant: f32 = b * b - a * c;
+
+    if discriminant > 0. {
+        // ray crosses the sphere at least once
+        let temp1: f32 = (-b - discriminant.sqrt()) / a;
+        let temp2: f32 = (-b + discriminant.sqrt()) / a;
+
+        // temp1の方がスクリーンに近い
+        for &t in &[temp1, temp2] {
+            if !(t_max > t && t > t_min) {
+                continue;
+            }
+            let p: Vec3 = r.point_at_parameter(t);
+            let normal: Vec3 = (p - center) / radius;
+            let (u, v) = sphere_uv(&((p - center) / radius.abs()));
+            let (dpdu, dpdv) = sphere_tangents(&(p - center));
+            let mut rec = HitRecord {
+                t,
+                p,
+                normal,
+                front_face: true,
+                u,
+                v,
+                dpdu,
+                dpdv,
+                material,
+            };
+            rec.set_face_normal(r, normal);
+            // through a hole, the far side may still be hit
+            if material.alpha_test(&rec) {
+                return Some(rec);
             }
-        } else {
-            // ray never crosses the sphere
-            None
         }
+        None
+    } else {
+        // ray never crosses the sphere
+        None
+    }
+}
+
+// Texture coordinates of a point `d` of the unit sphere: `u` is the longitude
+// around the y axis from -x, `v` the latitude from the bottom pole.
+fn sphere_uv(d: &Vec3) -> (f32, f32) {
+    let theta = (-d.y).clamp(-1.0, 1.0).acos();
+    let phi = (-d.z).atan2(d.x) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+// Derivatives along `u` and `v` of the point `d` of a sphere centered on the
+// origin, following `sphere_uv`
+fn sphere_tangents(d: &Vec3) -> (Vec3, Vec3) {
+    // distance from the y axis, zero at the poles
+    let s = (d.x * d.x + d.z * d.z).sqrt().max(1e-8);
+    let dpdu = 2.0 * PI * Vec3::new(d.z, 0.0, -d.x);
+    let dpdv = PI * Vec3::new(-d.y * d.x / s, s, -d.y * d.z / s);
+    (dpdu, dpdv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use std::sync::Arc;
+
+    #[test]
+    fn uv_and_bounding_box() {
+        let center = Vec3::new(1.0, 2.0, 3.0);
+        let sphere = Sphere::new(center, 2.0, Arc::new(Lambertian::new(0.5, 0.5, 0.5)));
+        let r = Ray::new(Vec3::new(-5.0, 2.0, 3.0), Vec3::new(1.0, 0.0, 0.0));
+        let rec = sphere.hit(&r, 0.0, f32::MAX).unwrap();
+        assert_eq!(rec.p, Vec3::new(-1.0, 2.0, 3.0));
+        assert!((rec.u - 0.0).abs() < 1e-6 || (rec.u - 1.0).abs() < 1e-6);
+        assert!((rec.v - 0.5).abs() < 1e-6);
+
+        let top = sphere.hit(
+            &Ray::new(Vec3::new(1.0, 9.0, 3.0), Vec3::new(0.0, -1.0, 0.0)),
+            0.0,
+            f32::MAX,
+        );
+        assert!((top.unwrap().v - 1.0).abs() < 1e-6);
+
+        let bbox = sphere.bounding_box(0.0, 0.0).unwrap();
+        assert_eq!(bbox.min, Vec3::new(-1.0, 0.0, 1.0));
+        assert_eq!(bbox.max, Vec3::new(3.0, 4.0, 5.0));
+    }
+
+    #[test]
+    fn tangents_follow_uv() {
+        let sphere = Sphere::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            2.0,
+            Arc::new(Lambertian::new(0.5, 0.5, 0.5)),
+        );
+        let r = Ray::new(Vec3::new(3.0, 4.0, 5.0), Vec3::new(-3.0, -3.5, -5.0));
+        let rec = sphere.hit(&r, 0.0, f32::MAX).unwrap();
+        assert!(rec.dpdu.dot(&rec.normal).abs() < 1e-4);
+        assert!(rec.dpdv.dot(&rec.normal).abs() < 1e-4);
+        assert!(rec.dpdu.cross(&rec.dpdv).dot(&rec.normal) > 0.0);
+
+        // a small step along a tangent moves the coordinates by as much
+        let uv_after = |step: Vec3| sphere_uv(&(rec.p + step).unit_vector());
+        let e = 1e-3;
+        let (u, v) = uv_after(e * rec.dpdu);
+        assert!((u - rec.u - e).abs() < 1e-4 && (v - rec.v).abs() < 1e-4);
+        let (u, v) = uv_after(e * rec.dpdv);
+        assert!((u - rec.u).abs() < 1e-4 && (v - rec.v - e).abs() < 1e-4);
+    }
+
+    #[test]
+    fn hit_through_cutout() {
+        use crate::material::{AlphaMode, Masked};
+        use crate::texture::{Checker, Texture};
+
+        // cut out where z > 0, in the even cells of a wide checker board
+        let (hole, solid): (Arc<dyn Texture>, Arc<dyn Texture>) = (Arc::new(0.0), Arc::new(1.0));
+        let material = Masked::new(
+            Arc::new(Lambertian::new(0.5, 0.5, 0.5)),
+            Checker::new(hole, solid, 100.0),
+            AlphaMode::Cutout(0.5),
+        );
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Arc::new(material));
+        let r = Ray::new(Vec3::new(0.5, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = sphere.hit(&r, 0.0, f32::MAX).unwrap();
+        // the inside of the far side
+        assert!(rec.p.z < 0.0 && rec.normal.z > 0.0 && !rec.front_face);
+        // and nothing seen from the other side
+        let r = Ray::new(Vec3::new(0.5, 0.5, 0.2), Vec3::new(0.0, 0.0, 1.0));
+        assert!(sphere.hit(&r, 0.0, f32::MAX).is_none());
     }
 }
@@ -0,0 +1,157 @@
+use std::cell::Cell;
+use std::fmt;
+use std::ops::{Add, AddAssign};
+use std::time::Duration;
+
+thread_local! {
+    static INTERSECTION_TESTS: Cell<u64> = const { Cell::new(0) };
+}
+
+// Record `n` ray-object intersection tests on the current thread.
+// Aggregates (e.g. `HitableList`) call this for the objects they test.
+pub fn add_intersection_tests(n: u64) {
+    INTERSECTION_TESTS.with(|c| c.set(c.get() + n));
+}
+
+// Number of intersection tests recorded on the current thread so far.
+// Take the difference of two readings to count the tests of one path.
+pub fn intersection_tests() -> u64 {
+    INTERSECTION_TESTS.with(|c| c.get())
+}
+
+// Ray counters of a set of paths
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct RayCounts {
+    // Rays leaving the camera
+    pub primary_rays: u64,
+    // Rays spawned by scattering at a surface
+    pub secondary_rays: u64,
+    pub intersection_tests: u64,
+}
+
+impl RayCounts {
+    pub fn total_rays(&self) -> u64 {
+        self.primary_rays + self.secondary_rays
+    }
+}
+
+impl Add for RayCounts {
+    type Output = RayCounts;
+
+    fn add(self, other: RayCounts) -> Self::Output {
+        RayCounts {
+            primary_rays: self.primary_rays + other.primary_rays,
+            secondary_rays: self.secondary_rays + other.secondary_rays,
+            intersection_tests: self.intersection_tests + other.intersection_tests,
+        }
+    }
+}
+
+impl AddAssign for RayCounts {
+    fn add_assign(&mut self, other: RayCounts) {
+        *self = *self + other;
+    }
+}
+
+// Statistics of a whole render: ray counters and the time spent in each phase.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RenderStats {
+    pub rays: RayCounts,
+    pub scene_build: Duration,
+    // building the acceleration structures
+    pub accel_build: Duration,
+    pub render: Duration,
+    pub write: Duration,
+}
+
+impl RenderStats {
+    // Average number of rays in a path, the primary ray included
+    pub fn average_path_length(&self) -> f64 {
+        ratio(self.rays.total_rays(), self.rays.primary_rays)
+    }
+
+    pub fn intersection_tests_per_ray(&self) -> f64 {
+        ratio(self.rays.intersection_tests, self.rays.total_rays())
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        let secs = self.render.as_secs_f64();
+        if secs > 0.0 {
+            self.rays.total_rays() as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    pub fn total_time(&self) -> Duration {
+        self.scene_build + self.accel_build + self.render + self.write
+    }
+}
+
+fn ratio(a: u64, b: u64) -> f64 {
+    if b > 0 {
+        a as f64 / b as f64
+    } else {
+        0.0
+    }
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Render statistics")?;
+        writeln!(f, "  primary rays        : {}", self.rays.primary_rays)?;
+        writeln!(f, "  secondary rays      : {}", self.rays.secondary_rays)?;
+        writeln!(
+            f,
+            "  average path length : {:.3}",
+            self.average_path_length()
+        )?;
+        writeln!(
+            f,
+            "  tests per ray       : {:.1}",
+            self.intersection_tests_per_ray()
+        )?;
+        writeln!(
+            f,
+            "  rays per second     : {:.3} M",
+            self.rays_per_second() / 1.0e6
+        )?;
+        writeln!(f, "  scene build         : {:?}", self.scene_build)?;
+        writeln!(f, "  acceleration build  : {:?}", self.accel_build)?;
+        writeln!(f, "  render              : {:?}", self.render)?;
+        writeln!(f, "  write               : {:?}", self.write)?;
+        write!(f, "  total               : {:?}", self.total_time())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derived_statistics() {
+        let stats = RenderStats {
+            rays: RayCounts {
+                primary_rays: 100,
+                secondary_rays: 150,
+                intersection_tests: 5000,
+            },
+            render: Duration::from_millis(500),
+            ..Default::default()
+        };
+        assert_eq!(stats.average_path_length(), 2.5);
+        assert_eq!(stats.intersection_tests_per_ray(), 20.0);
+        assert_eq!(stats.rays_per_second(), 500.0);
+        assert_eq!(RenderStats::default().average_path_length(), 0.0);
+    }
+
+    #[test]
+    fn intersection_tests_are_per_thread() {
+        let before = intersection_tests();
+        add_intersection_tests(3);
+        std::thread::spawn(|| add_intersection_tests(10))
+            .join()
+            .unwrap();
+        assert_eq!(intersection_tests() - before, 3);
+    }
+}
@@ -0,0 +1,146 @@
+use crate::image::Image;
+use crate::perlin::Perlin;
+use crate::vec3::Vec3;
+
+use std::sync::Arc;
+
+// Value varying over a surface, looked up by the surface coordinates (u, v)
+// of `HitRecord` or by the point hit. Parameters that are a single number
+// take the first channel.
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f32, v: f32, p: &Vec3) -> Vec3;
+}
+
+// Constant color
+impl Texture for Vec3 {
+    fn value(&self, _u: f32, _v: f32, _p: &Vec3) -> Vec3 {
+        *self
+    }
+}
+
+// Constant number
+impl Texture for f32 {
+    fn value(&self, _u: f32, _v: f32, _p: &Vec3) -> Vec3 {
+        Vec3::new(*self, *self, *self)
+    }
+}
+
+// Textures shared by several materials
+impl<T: Texture + ?Sized> Texture for Arc<T> {
+    fn value(&self, u: f32, v: f32, p: &Vec3) -> Vec3 {
+        (**self).value(u, v, p)
+    }
+}
+
+// 3D checker board of cubes of side `size`, filling space so that it does
+// not depend on the surface coordinates
+pub struct Checker {
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+    pub size: f32,
+}
+
+impl Checker {
+    pub fn new(even: Arc<dyn Texture>, odd: Arc<dyn Texture>, size: f32) -> Self {
+        Checker { even, odd, size }
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f32, v: f32, p: &Vec3) -> Vec3 {
+        let cell = |x: f32| (x / self.size).floor() as i64;
+        if (cell(p.x) + cell(p.y) + cell(p.z)).rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+// Image stretched over the surface coordinates, repeated outside [0, 1] and
+// filtered bilinearly. v = 0 is the bottom row.
+pub struct ImageTexture {
+    pub image: Arc<Image>,
+}
+
+impl ImageTexture {
+    pub fn new(image: Arc<Image>) -> Self {
+        ImageTexture { image }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f32, v: f32, _p: &Vec3) -> Vec3 {
+        let (w, h) = (self.image.width, self.image.height);
+        if w == 0 || h == 0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        // pixel centers are at half integers
+        let x = u.rem_euclid(1.0) * w as f32 - 0.5;
+        let y = (1.0 - v.rem_euclid(1.0)) * h as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let wrap = |i: f32, n: u32| (i as i64).rem_euclid(n as i64) as u32;
+        let texel = |dx: f32, dy: f32| self.image.get(wrap(x0 + dx, w), wrap(y0 + dy, h));
+        (1.0 - fy) * ((1.0 - fx) * texel(0.0, 0.0) + fx * texel(1.0, 0.0))
+            + fy * ((1.0 - fx) * texel(0.0, 1.0) + fx * texel(1.0, 1.0))
+    }
+}
+
+// Marble-like bands of `color` disturbed by turbulence
+pub struct NoiseTexture {
+    noise: Perlin,
+    pub color: Vec3,
+    pub scale: f32,
+}
+
+impl NoiseTexture {
+    pub fn new(color: Vec3, scale: f32, seed: u64) -> Self {
+        NoiseTexture {
+            noise: Perlin::new(seed),
+            color,
+            scale,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f32, _v: f32, p: &Vec3) -> Vec3 {
+        let phase = self.scale * p.z + 10.0 * self.noise.turbulence(p, 7);
+        self.color * (0.5 * (1.0 + phase.sin()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checker_and_constants() {
+        let white: Arc<dyn Texture> = Arc::new(1.0);
+        let red: Arc<dyn Texture> = Arc::new(Vec3::new(1.0, 0.0, 0.0));
+        let checker = Checker::new(white, red, 0.5);
+        let at = |x, y, z| checker.value(0.0, 0.0, &Vec3::new(x, y, z));
+        assert_eq!(at(0.1, 0.1, 0.1), Vec3::new(1.0, 1.0, 1.0));
+        assert_eq!(at(0.6, 0.1, 0.1), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(at(-0.1, 0.1, 0.1), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(at(-0.1, -0.1, 0.1), Vec3::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn bilinear_image_lookup() {
+        let mut image = Image::new(2, 2);
+        image.set(0, 0, Vec3::new(1.0, 0.0, 0.0));
+        image.set(1, 0, Vec3::new(0.0, 1.0, 0.0));
+        let texture = ImageTexture::new(Arc::new(image));
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+
+        // texel centers, the top row being v = 1
+        assert_eq!(texture.value(0.25, 0.75, &origin), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(texture.value(0.75, 0.25, &origin), Vec3::new(0.0, 0.0, 0.0));
+        // halfway between the two top texels, and repeated
+        let mid = texture.value(0.5, 0.75, &origin);
+        assert!((mid - Vec3::new(0.5, 0.5, 0.0)).length() < 1e-6);
+        assert_eq!(texture.value(1.5, 0.75, &origin), mid);
+    }
+}
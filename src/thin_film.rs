@@ -0,0 +1,200 @@
+use crate::hitable::HitRecord;
+use crate::spectrum;
+use crate::texture::Texture;
+use crate::vec3::Vec3;
+
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+// Wavelengths standing for the red, green and blue channels in RGB rendering,
+// in nanometers
+const RGB_WAVELENGTHS: [f32; 3] = [630.0, 532.0, 465.0];
+
+// Transparent layer a few hundred nanometers thick on a surface, such as soap,
+// oil or the oxide of anodized metal. Light reflected at its two sides
+// interferes, so that the reflectance depends on the wavelength, the angle and
+// the thickness, which gives the iridescent colors.
+pub struct ThinFilm {
+    // in nanometers, the first channel
+    pub thickness: Arc<dyn Texture>,
+    pub ior: f32,
+}
+
+// What is under the film
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Substrate {
+    // transparent, of this index
+    Dielectric(f32),
+    // opaque, reflecting this color like `Metal`, with the phase shift of
+    // metals
+    Mirror(Vec3),
+}
+
+impl ThinFilm {
+    pub fn new<T: Texture + 'static>(thickness: T, ior: f32) -> Self {
+        ThinFilm {
+            thickness: Arc::new(thickness),
+            ior,
+        }
+    }
+
+    // Reflectance at `rec` for light arriving at the cosine `cos_i` from the
+    // medium of index `n_outside`. At the wavelength of a spectral ray the
+    // channels are equal; in RGB, each is at one of `RGB_WAVELENGTHS`.
+    pub(crate) fn reflectance(
+        &self,
+        rec: &HitRecord,
+        cos_i: f32,
+        n_outside: f32,
+        substrate: Substrate,
+        wavelength: Option<f32>,
+    ) -> Vec3 {
+        let thickness = self.thickness.value(rec.u, rec.v, &rec.p).x.max(0.0);
+        let at = |lambda: f32, albedo: f32| {
+            // one channel of the mirror at a time
+            let substrate = match substrate {
+                Substrate::Mirror(_) => Substrate::Mirror(Vec3::new(albedo, albedo, albedo)),
+                dielectric => dielectric,
+            };
+            airy(cos_i, n_outside, self.ior, substrate, thickness, lambda)
+        };
+        let albedo = match substrate {
+            Substrate::Mirror(albedo) => albedo,
+            Substrate::Dielectric(_) => Vec3::new(1.0, 1.0, 1.0),
+        };
+        match wavelength {
+            Some(lambda) => {
+                let r = at(lambda, spectrum::rgb_to_spectrum_at(&albedo, lambda));
+                Vec3::new(r, r, r)
+            }
+            None => Vec3::new(
+                at(RGB_WAVELENGTHS[0], albedo.x),
+                at(RGB_WAVELENGTHS[1], albedo.y),
+                at(RGB_WAVELENGTHS[2], albedo.z),
+            ),
+        }
+    }
+}
+
+// Amplitude reflection coefficients (s, p) from index `n_i` into `n_t`
+fn fresnel_amplitudes(n_i: f32, cos_i: f32, n_t: f32, cos_t: f32) -> (f32, f32) {
+    (
+        (n_i * cos_i - n_t * cos_t) / (n_i * cos_i + n_t * cos_t),
+        (n_t * cos_i - n_i * cos_t) / (n_t * cos_i + n_i * cos_t),
+    )
+}
+
+// Cosine of the refracted angle by Snell's law, or `None` on total reflection
+fn refracted_cosine(n_i: f32, cos_i: f32, n_t: f32) -> Option<f32> {
+    let sin2_t = (n_i / n_t).powi(2) * (1.0 - cos_i * cos_i).max(0.0);
+    if sin2_t >= 1.0 {
+        None
+    } else {
+        Some((1.0 - sin2_t).sqrt())
+    }
+}
+
+// Reflectance of a film of index `n_film` and `thickness` in nanometers at
+// the wavelength `lambda`, summing the waves reflected back and forth inside
+// it (Airy), averaged over the polarizations
+fn airy(
+    cos_i: f32,
+    n_outside: f32,
+    n_film: f32,
+    substrate: Substrate,
+    thickness: f32,
+    lambda: f32,
+) -> f32 {
+    let cos_i = cos_i.abs().min(1.0);
+    let cos_film = match refracted_cosine(n_outside, cos_i, n_film) {
+        Some(c) => c,
+        None => return 1.0,
+    };
+    let (r12_s, r12_p) = fresnel_amplitudes(n_outside, cos_i, n_film, cos_film);
+    let (r23_s, r23_p) = match substrate {
+        Substrate::Dielectric(n) => match refracted_cosine(n_film, cos_film, n) {
+            Some(cos_t) => fresnel_amplitudes(n_film, cos_film, n, cos_t),
+            // nothing goes through: all is reflected whatever the film
+            None => return 1.0,
+        },
+        Substrate::Mirror(albedo) => {
+            let r = -albedo.x.clamp(0.0, 1.0).sqrt();
+            (r, r)
+        }
+    };
+    // phase difference of one round trip in the film
+    let delta = 4.0 * PI * n_film * thickness * cos_film / lambda;
+    let airy = |r12: f32, r23: f32| {
+        let cross = 2.0 * r12 * r23 * delta.cos();
+        (r12 * r12 + r23 * r23 + cross) / (1.0 + r12 * r12 * r23 * r23 + cross)
+    };
+    (0.5 * (airy(r12_s, r23_s) + airy(r12_p, r23_p))).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::microfacet;
+
+    #[test]
+    fn interference() {
+        // a film of the index outside is the bare interface
+        for &cos in &[1.0f32, 0.7, 0.2] {
+            let bare = airy(cos, 1.0, 1.0, Substrate::Dielectric(1.5), 300.0, 550.0);
+            assert!((bare - microfacet::fresnel_dielectric(cos, 1.5)).abs() < 1e-5);
+        }
+
+        // a quarter wave coating of index √1.5 cancels the reflection
+        let n = 1.5f32.sqrt();
+        let coating = airy(
+            1.0,
+            1.0,
+            n,
+            Substrate::Dielectric(1.5),
+            550.0 / (4.0 * n),
+            550.0,
+        );
+        assert!(coating < 1e-6, "{}", coating);
+        // and a half wave one is as if absent
+        let half = airy(
+            1.0,
+            1.0,
+            n,
+            Substrate::Dielectric(1.5),
+            550.0 / (2.0 * n),
+            550.0,
+        );
+        assert!((half - 0.04).abs() < 1e-5);
+
+        // total reflection from inside glass
+        assert_eq!(
+            airy(0.2, 1.5, 1.33, Substrate::Dielectric(1.0), 300.0, 550.0),
+            1.0
+        );
+    }
+
+    #[test]
+    fn colors_vary_with_thickness() {
+        use crate::material::Lambertian;
+
+        let material = Lambertian::new(0.5, 0.5, 0.5);
+        let rec = HitRecord::facing_z(&material);
+        let bubble = Substrate::Dielectric(1.0);
+        let thin = ThinFilm::new(250.0, 1.33).reflectance(&rec, 1.0, 1.0, bubble, None);
+        let thick = ThinFilm::new(400.0, 1.33).reflectance(&rec, 1.0, 1.0, bubble, None);
+        assert!((thin - thick).length() > 0.05, "{:?} {:?}", thin, thick);
+        assert!(thin.x != thin.y && thin.y != thin.z);
+
+        // a spectral ray sees one gray reflectance
+        let spectral = ThinFilm::new(250.0, 1.33).reflectance(&rec, 1.0, 1.0, bubble, Some(630.0));
+        assert_eq!(spectral, Vec3::new(thin.x, thin.x, thin.x));
+
+        // a perfect mirror reflects everything, a dull one in colors
+        let mirror = Substrate::Mirror(Vec3::new(1.0, 1.0, 1.0));
+        let anodized = ThinFilm::new(300.0, 2.0).reflectance(&rec, 0.8, 1.0, mirror, None);
+        assert!((anodized - Vec3::new(1.0, 1.0, 1.0)).length() < 1e-5);
+        let mirror = Substrate::Mirror(Vec3::new(0.6, 0.6, 0.6));
+        let anodized = ThinFilm::new(300.0, 2.0).reflectance(&rec, 0.8, 1.0, mirror, None);
+        assert!(anodized.x != anodized.z);
+    }
+}
@@ -0,0 +1,406 @@
+use crate::aabb::Aabb;
+use crate::quaternion::Quat;
+use crate::vec3::Vec3;
+
+use std::ops::Mul;
+
+// 4x4 matrix acting on column vectors, stored by rows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f32; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn identity() -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Mat4 { m }
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = self.m[j][i];
+            }
+        }
+        Mat4 { m }
+    }
+
+    // Gauss-Jordan elimination with partial pivoting. `None` if the matrix
+    // is singular.
+    pub fn inverse(&self) -> Option<Mat4> {
+        let mut a = self.m.map(|row| row.map(f64::from));
+        let mut inv = Mat4::identity().m.map(|row| row.map(f64::from));
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+                .unwrap();
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+            let p = a[col][col];
+            for j in 0..4 {
+                a[col][j] /= p;
+                inv[col][j] /= p;
+            }
+            for i in 0..4 {
+                if i != col {
+                    let f = a[i][col];
+                    for j in 0..4 {
+                        a[i][j] -= f * a[col][j];
+                        inv[i][j] -= f * inv[col][j];
+                    }
+                }
+            }
+        }
+        Some(Mat4 {
+            m: inv.map(|row| row.map(|x| x as f32)),
+        })
+    }
+
+    pub fn transform_point(&self, p: &Vec3) -> Vec3 {
+        let m = &self.m;
+        let x = m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3];
+        let y = m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3];
+        let z = m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3];
+        let w = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+        if w == 1.0 {
+            Vec3::new(x, y, z)
+        } else {
+            Vec3::new(x, y, z) / w
+        }
+    }
+
+    // Transform a direction, ignoring the translation
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        Mat4 { m }
+    }
+}
+
+// Affine transform with its inverse
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub matrix: Mat4,
+    pub inverse: Mat4,
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Transform {
+            matrix: Mat4::identity(),
+            inverse: Mat4::identity(),
+        }
+    }
+
+    // `None` if the matrix is not invertible
+    pub fn from_matrix(matrix: Mat4) -> Option<Self> {
+        Some(Transform {
+            matrix,
+            inverse: matrix.inverse()?,
+        })
+    }
+
+    pub fn translate(offset: Vec3) -> Self {
+        let mut t = Transform::identity();
+        for axis in 0..3 {
+            t.matrix.m[axis][3] = offset[axis];
+            t.inverse.m[axis][3] = -offset[axis];
+        }
+        t
+    }
+
+    // Rotation of `degrees` around `axis`, counterclockwise when the axis
+    // points towards the viewer
+    pub fn rotate(axis: Vec3, degrees: f32) -> Self {
+        let a = axis.unit_vector();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let mut matrix = Mat4::identity();
+        // Rodrigues' rotation formula
+        matrix.m[0][0] = cos + a.x * a.x * (1.0 - cos);
+        matrix.m[0][1] = a.x * a.y * (1.0 - cos) - a.z * sin;
+        matrix.m[0][2] = a.x * a.z * (1.0 - cos) + a.y * sin;
+        matrix.m[1][0] = a.y * a.x * (1.0 - cos) + a.z * sin;
+        matrix.m[1][1] = cos + a.y * a.y * (1.0 - cos);
+        matrix.m[1][2] = a.y * a.z * (1.0 - cos) - a.x * sin;
+        matrix.m[2][0] = a.z * a.x * (1.0 - cos) - a.y * sin;
+        matrix.m[2][1] = a.z * a.y * (1.0 - cos) + a.x * sin;
+        matrix.m[2][2] = cos + a.z * a.z * (1.0 - cos);
+        Transform {
+            matrix,
+            inverse: matrix.transpose(),
+        }
+    }
+
+    // Non-uniform scale. The factors must not be zero.
+    pub fn scale(factors: Vec3) -> Self {
+        let mut t = Transform::identity();
+        for axis in 0..3 {
+            t.matrix.m[axis][axis] = factors[axis];
+            t.inverse.m[axis][axis] = 1.0 / factors[axis];
+        }
+        t
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform {
+            matrix: self.inverse,
+            inverse: self.matrix,
+        }
+    }
+
+    // Apply `self`, then `next`
+    pub fn then(&self, next: &Transform) -> Transform {
+        *next * *self
+    }
+
+    pub fn point(&self, p: &Vec3) -> Vec3 {
+        self.matrix.transform_point(p)
+    }
+
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        self.matrix.transform_vector(v)
+    }
+
+    // Transform a normal with the inverse transpose, so that it stays
+    // perpendicular to the transformed surface. The result is not normalized.
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        self.inverse.transpose().transform_vector(n)
+    }
+
+    // Split into translation, rotation and the remaining scale and shear,
+    // with `matrix = translate * rotate * scale`
+    pub fn decompose(&self) -> Decomposition {
+        let m = &self.matrix.m;
+        let translation = Vec3::new(m[0][3], m[1][3], m[2][3]);
+        let mut linear = self.matrix;
+        for i in 0..3 {
+            linear.m[i][3] = 0.0;
+        }
+
+        // Polar decomposition: averaging a matrix with its inverse transpose
+        // converges to the nearest orthogonal matrix
+        let mut rotation = linear;
+        for _ in 0..100 {
+            let inverse_transpose = match rotation.inverse() {
+                Some(inverse) => inverse.transpose(),
+                None => break,
+            };
+            let mut change: f32 = 0.0;
+            for i in 0..3 {
+                for j in 0..3 {
+                    let next = 0.5 * (rotation.m[i][j] + inverse_transpose.m[i][j]);
+                    change = change.max((next - rotation.m[i][j]).abs());
+                    rotation.m[i][j] = next;
+                }
+            }
+            if change < 1e-6 {
+                break;
+            }
+        }
+        // a mirror image goes into the scale, keeping a proper rotation
+        if determinant3(&rotation) < 0.0 {
+            for row in rotation.m.iter_mut().take(3) {
+                for x in row.iter_mut().take(3) {
+                    *x = -*x;
+                }
+            }
+        }
+        let scale = rotation.transpose() * linear;
+
+        Decomposition {
+            translation,
+            rotation: Quat::from_rotation(&rotation),
+            scale,
+        }
+    }
+
+    // Box containing the transformed corners of `b`
+    pub fn bounding_box(&self, b: &Aabb) -> Aabb {
+        let corner = |i: usize| {
+            Vec3::new(
+                if i & 1 == 0 { b.min.x } else { b.max.x },
+                if i & 2 == 0 { b.min.y } else { b.max.y },
+                if i & 4 == 0 { b.min.z } else { b.max.z },
+            )
+        };
+        let first = self.point(&corner(0));
+        (1..8).fold(Aabb::new(first, first), |acc, i| {
+            let p = self.point(&corner(i));
+            acc.surrounding(&Aabb::new(p, p))
+        })
+    }
+}
+
+// Determinant of the upper 3x3 block
+fn determinant3(m: &Mat4) -> f32 {
+    let m = &m.m;
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+// Parts of an affine transform that can be interpolated separately
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decomposition {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    // scale and shear, in the upper 3x3 block
+    pub scale: Mat4,
+}
+
+impl Decomposition {
+    // Interpolate between `self` at 0 and `other` at 1: linearly for the
+    // translation and the scale, along the shortest arc for the rotation
+    pub fn interpolate(&self, other: &Decomposition, t: f32) -> Decomposition {
+        let mut scale = self.scale;
+        for (row, other_row) in scale.m.iter_mut().zip(&other.scale.m) {
+            for (x, y) in row.iter_mut().zip(other_row) {
+                *x += t * (y - *x);
+            }
+        }
+        Decomposition {
+            translation: self.translation + t * (other.translation - self.translation),
+            rotation: self.rotation.slerp(&other.rotation, t),
+            scale,
+        }
+    }
+
+    // `None` if the scale is singular, which interpolation between invertible
+    // transforms only gives when one of them is mirrored and the other not
+    pub fn to_transform(&self) -> Option<Transform> {
+        let rotation = self.rotation.to_matrix();
+        let linear = Transform {
+            matrix: rotation * self.scale,
+            inverse: self.scale.inverse()? * rotation.transpose(),
+        };
+        Some(Transform::translate(self.translation) * linear)
+    }
+}
+
+// `a * b` applies `b` first, then `a`
+impl Mul for Transform {
+    type Output = Transform;
+
+    fn mul(self, other: Transform) -> Transform {
+        Transform {
+            matrix: self.matrix * other.matrix,
+            inverse: other.inverse * self.inverse,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn basic_transforms() {
+        let p = Vec3::new(1.0, 2.0, 3.0);
+        let t = Transform::translate(Vec3::new(1.0, -1.0, 0.5));
+        assert_close(t.point(&p), Vec3::new(2.0, 1.0, 3.5));
+        assert_close(t.vector(&p), p);
+
+        let r = Transform::rotate(Vec3::new(0.0, 0.0, 2.0), 90.0);
+        assert_close(r.point(&Vec3::new(1.0, 0.0, 0.0)), Vec3::new(0.0, 1.0, 0.0));
+        let r = Transform::rotate(Vec3::new(1.0, 1.0, 1.0), 120.0);
+        assert_close(r.point(&Vec3::new(1.0, 0.0, 0.0)), Vec3::new(0.0, 1.0, 0.0));
+
+        let s = Transform::scale(Vec3::new(2.0, 3.0, -1.0));
+        assert_close(s.point(&p), Vec3::new(2.0, 6.0, -3.0));
+    }
+
+    #[test]
+    fn composition_and_inverse() {
+        let t = Transform::scale(Vec3::new(2.0, 2.0, 2.0))
+            .then(&Transform::rotate(Vec3::new(0.0, 1.0, 0.0), 90.0))
+            .then(&Transform::translate(Vec3::new(0.0, 0.0, 5.0)));
+        let p = Vec3::new(1.0, 0.0, 0.0);
+        assert_close(t.point(&p), Vec3::new(0.0, 0.0, 3.0));
+        assert_close(t.inverse().point(&t.point(&p)), p);
+
+        let inverse = Transform::from_matrix(t.matrix).unwrap().inverse;
+        for (row, expected) in inverse.m.iter().zip(&t.inverse.m) {
+            for (x, y) in row.iter().zip(expected) {
+                assert!((x - y).abs() < 1e-5);
+            }
+        }
+        let mut singular = Mat4::identity();
+        singular.m[1][1] = 0.0;
+        assert!(Transform::from_matrix(singular).is_none());
+    }
+
+    #[test]
+    fn normals_stay_perpendicular() {
+        let t = Transform::scale(Vec3::new(4.0, 1.0, 1.0))
+            .then(&Transform::rotate(Vec3::new(0.0, 0.0, 1.0), 30.0));
+        // the plane x + y = 0
+        let tangent = Vec3::new(1.0, -1.0, 0.0);
+        let normal = Vec3::new(1.0, 1.0, 0.0);
+        assert!(t.normal(&normal).dot(&t.vector(&tangent)).abs() < 1e-5);
+        assert!(t.vector(&normal).dot(&t.vector(&tangent)).abs() > 1.0);
+    }
+
+    #[test]
+    fn decompose_and_recompose() {
+        let t = Transform::scale(Vec3::new(2.0, 1.0, 0.5))
+            .then(&Transform::rotate(Vec3::new(1.0, 1.0, 0.0), 40.0))
+            .then(&Transform::translate(Vec3::new(1.0, 2.0, 3.0)));
+        let parts = t.decompose();
+        assert_close(parts.translation, Vec3::new(1.0, 2.0, 3.0));
+        let angle = parts.rotation.angle_to(&Quat::identity()).to_degrees();
+        assert!((angle - 40.0).abs() < 1e-2);
+        assert!((parts.scale.m[0][0] - 2.0).abs() < 1e-4);
+
+        // mirrored, with shear
+        let mut sheared = Mat4::identity();
+        sheared.m[0][1] = 0.5;
+        let mirrored = Transform::from_matrix(sheared).unwrap()
+            * Transform::scale(Vec3::new(1.0, -1.0, 3.0))
+                .then(&Transform::rotate(Vec3::new(0.0, 1.0, 1.0), -70.0))
+                .then(&Transform::translate(Vec3::new(1.0, 0.0, 0.0)));
+        for t in &[t, mirrored] {
+            let back = t.decompose().to_transform().unwrap();
+            let p = Vec3::new(0.5, -1.0, 2.0);
+            assert_close(back.point(&p), t.point(&p));
+            assert_close(back.inverse().point(&t.point(&p)), p);
+        }
+    }
+
+    #[test]
+    fn transformed_bounding_box() {
+        let b = Aabb::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0));
+        let t = Transform::rotate(Vec3::new(0.0, 0.0, 1.0), 45.0);
+        let bbox = t.bounding_box(&b);
+        let h = std::f32::consts::FRAC_1_SQRT_2;
+        assert_close(bbox.min, Vec3::new(-h, 0.0, 0.0));
+        assert_close(bbox.max, Vec3::new(h, 2.0 * h, 1.0));
+    }
+}
@@ -0,0 +1,96 @@
+use crate::aabb::Aabb;
+use crate::hitable::{HitRecord, Hitable};
+use crate::ray::Ray;
+use crate::transform::Transform;
+
+// Object moved by an affine transform. Rays are brought into the space of
+// the object, and the hit points and normals back into world space.
+pub struct Transformed<H: Hitable> {
+    pub object: H,
+    pub transform: Transform,
+}
+
+impl<H: Hitable> Transformed<H> {
+    pub fn new(object: H, transform: Transform) -> Self {
+        Transformed { object, transform }
+    }
+}
+
+impl<H: Hitable> Hitable for Transformed<H> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        hit_transformed(&self.object, &self.transform, r, t_min, t_max)
+    }
+
+    fn bounding_box(&self, time0: f32, time1: f32) -> Option<Aabb> {
+        let b = self.object.bounding_box(time0, time1)?;
+        Some(self.transform.bounding_box(&b))
+    }
+}
+
+// Intersection with `object` moved by `transform`, shared with `Instance`
+pub(crate) fn hit_transformed<'a, H: Hitable + ?Sized>(
+    object: &'a H,
+    transform: &Transform,
+    r: &Ray,
+    t_min: f32,
+    t_max: f32,
+) -> Option<HitRecord<'a>> {
+    let inverse = transform.inverse();
+    // The direction is not normalized, so that `t` is the same in both spaces
+    let local = Ray::with_time(
+        inverse.point(&r.origin),
+        inverse.vector(&r.direction),
+        r.time,
+    );
+    let mut rec = object.hit(&local, t_min, t_max)?;
+    rec.p = r.point_at_parameter(rec.t);
+    rec.normal = transform.normal(&rec.normal).unit_vector();
+    rec.dpdu = transform.vector(&rec.dpdu);
+    rec.dpdv = transform.vector(&rec.dpdv);
+    Some(rec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::vec3::Vec3;
+    use std::sync::Arc;
+
+    fn unit_sphere() -> Sphere {
+        Sphere::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            1.0,
+            Arc::new(Lambertian::new(0.5, 0.5, 0.5)),
+        )
+    }
+
+    #[test]
+    fn hit_scaled_and_moved_sphere() {
+        // ellipsoid with semi-axes 2, 1, 1 centered on (0, 0, -5)
+        let t = Transform::scale(Vec3::new(2.0, 1.0, 1.0))
+            .then(&Transform::translate(Vec3::new(0.0, 0.0, -5.0)));
+        let ellipsoid = Transformed::new(unit_sphere(), t);
+
+        let r = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = ellipsoid.hit(&r, 0.001, f32::MAX).unwrap();
+        assert!((rec.t - 4.0).abs() < 1e-5);
+        assert!((rec.p - Vec3::new(0.0, 0.0, -4.0)).length() < 1e-5);
+
+        // (0.6, 0, 0.8) on the sphere is (1.2, 0, -4.2) on the ellipsoid
+        let r = Ray::new(Vec3::new(1.2, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = ellipsoid.hit(&r, 0.001, f32::MAX).unwrap();
+        assert!((rec.p - Vec3::new(1.2, 0.0, -4.2)).length() < 1e-5);
+        let expected = Vec3::new(0.3, 0.0, 0.8).unit_vector();
+        assert!((rec.normal - expected).length() < 1e-5);
+        assert!((rec.normal.length() - 1.0).abs() < 1e-5);
+
+        let miss = Ray::new(Vec3::new(2.1, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(ellipsoid.hit(&miss, 0.001, f32::MAX).is_none());
+
+        let bbox = ellipsoid.bounding_box(0.0, 0.0).unwrap();
+        assert!((bbox.min - Vec3::new(-2.0, -1.0, -6.0)).length() < 1e-5);
+        assert!((bbox.max - Vec3::new(2.0, 1.0, -4.0)).length() < 1e-5);
+    }
+}
@@ -1,4 +1,4 @@
-use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::ops::{Add, Div, Index, Mul, Neg, Sub};
 use std::ops::{AddAssign, DivAssign};
 
 #[derive(Debug, Default, PartialEq, Copy, Clone)]
@@ -62,6 +62,38 @@ impl Vec3 {
     pub fn unit_vector(&self) -> Vec3 {
         *self / self.length()
     }
+
+    // Componentwise minimum
+    pub fn min(&self, other: &Vec3) -> Vec3 {
+        Vec3::new(
+            self.x.min(other.x),
+            self.y.min(other.y),
+            self.z.min(other.z),
+        )
+    }
+
+    // Componentwise maximum
+    pub fn max(&self, other: &Vec3) -> Vec3 {
+        Vec3::new(
+            self.x.max(other.x),
+            self.y.max(other.y),
+            self.z.max(other.z),
+        )
+    }
+}
+
+// Component by axis: 0 for x, 1 for y, 2 for z
+impl Index<usize> for Vec3 {
+    type Output = f32;
+
+    fn index(&self, axis: usize) -> &f32 {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("axis {} out of range", axis),
+        }
+    }
 }
 
 impl Add for Vec3 {
@@ -295,4 +327,13 @@ mod tests {
         let v_expected = Vec3::new(-1.0, -2.0, -3.0);
         assert_eq!(-v1, v_expected);
     }
+
+    #[test]
+    fn v_min_max_index() {
+        let v1 = Vec3::new(1.0, 5.0, -2.0);
+        let v2 = Vec3::new(3.0, 2.0, -1.0);
+        assert_eq!(v1.min(&v2), Vec3::new(1.0, 2.0, -2.0));
+        assert_eq!(v1.max(&v2), Vec3::new(3.0, 5.0, -1.0));
+        assert_eq!((v1[0], v1[1], v1[2]), (1.0, 5.0, -2.0));
+    }
 }
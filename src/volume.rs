@@ -0,0 +1,394 @@
+use crate::aabb::Aabb;
+use crate::error::{Error, Result};
+use crate::hitable::{HitRecord, Hitable};
+use crate::material::{HenyeyGreenstein, Material};
+use crate::perlin::Perlin;
+use crate::random;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+use std::borrow::Borrow;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+
+// Densities sampled on a regular 3D grid, interpolated trilinearly. The
+// samples are at the corners of the cells: (0, 0, 0) and (nx-1, ny-1, nz-1)
+// are at the corners of the volume.
+#[derive(Debug, Clone)]
+pub struct DensityGrid {
+    pub nx: usize,
+    pub ny: usize,
+    pub nz: usize,
+    // x varies fastest, then y, then z
+    pub data: Vec<f32>,
+    max: f32,
+}
+
+impl DensityGrid {
+    pub fn new(nx: usize, ny: usize, nz: usize, data: Vec<f32>) -> Result<Self> {
+        if nx < 2 || ny < 2 || nz < 2 {
+            return Err(Error::InvalidScene(format!(
+                "a density grid needs at least 2 samples per axis, got {}x{}x{}",
+                nx, ny, nz
+            )));
+        }
+        if data.len() != nx * ny * nz {
+            return Err(Error::InvalidScene(format!(
+                "a {}x{}x{} density grid needs {} values, got {}",
+                nx,
+                ny,
+                nz,
+                nx * ny * nz,
+                data.len()
+            )));
+        }
+        if data.iter().any(|d| !(d.is_finite() && *d >= 0.0)) {
+            return Err(Error::InvalidScene(
+                "densities must be finite and positive".to_string(),
+            ));
+        }
+        let max = data.iter().cloned().fold(0.0, f32::max);
+        Ok(DensityGrid {
+            nx,
+            ny,
+            nz,
+            data,
+            max,
+        })
+    }
+
+    // Sample `density` at the grid points, given in [0, 1]^3
+    pub fn from_fn<F: Fn(&Vec3) -> f32>(
+        nx: usize,
+        ny: usize,
+        nz: usize,
+        density: F,
+    ) -> Result<Self> {
+        let coord = |i: usize, n: usize| i as f32 / (n.max(2) - 1) as f32;
+        let mut data = Vec::with_capacity(nx * ny * nz);
+        for k in 0..nz {
+            for j in 0..ny {
+                for i in 0..nx {
+                    data.push(density(&Vec3::new(
+                        coord(i, nx),
+                        coord(j, ny),
+                        coord(k, nz),
+                    )));
+                }
+            }
+        }
+        DensityGrid::new(nx, ny, nz, data)
+    }
+
+    // Billowy cloud fading out towards the sides of the volume, with
+    // densities in [0, 1]
+    pub fn cloud(resolution: usize, seed: u64) -> Result<Self> {
+        let noise = Perlin::new(seed);
+        DensityGrid::from_fn(resolution, resolution, resolution, |p| {
+            let centered = 2.0 * *p - Vec3::new(1.0, 1.0, 1.0);
+            let falloff = 1.0 - centered.length();
+            let shape = falloff + 0.6 * noise.turbulence(&(*p * 4.0), 5) - 0.4;
+            (2.0 * shape).clamp(0.0, 1.0)
+        })
+    }
+
+    // Read the raw grid format: the text header `density_grid NX NY NZ`,
+    // a single newline, then NX * NY * NZ little-endian 32-bit floats with x
+    // varying fastest
+    pub fn read_raw<R: Read>(r: &mut R) -> Result<Self> {
+        let mut data: Vec<u8> = vec![];
+        r.read_to_end(&mut data)?;
+        let newline = data
+            .iter()
+            .position(|&c| c == b'\n')
+            .ok_or_else(|| Error::Parse("missing density grid header".to_string()))?;
+        let header = std::str::from_utf8(&data[..newline])
+            .map_err(|_| Error::Parse("invalid density grid header".to_string()))?;
+        let fields: Vec<&str> = header.split_whitespace().collect();
+        let dims: Vec<usize> = match fields.as_slice() {
+            ["density_grid", dims @ ..] if dims.len() == 3 => dims
+                .iter()
+                .map(|d| d.parse::<usize>())
+                .collect::<std::result::Result<_, _>>()
+                .map_err(|_| Error::Parse(format!("invalid grid size in '{}'", header)))?,
+            _ => {
+                return Err(Error::Parse(format!(
+                    "expected 'density_grid NX NY NZ', got '{}'",
+                    header
+                )))
+            }
+        };
+
+        let body = &data[newline + 1..];
+        let count = dims[0]
+            .checked_mul(dims[1])
+            .and_then(|n| n.checked_mul(dims[2]))
+            .ok_or_else(|| Error::Parse("density grid too large".to_string()))?;
+        if body.len() != 4 * count {
+            return Err(Error::Parse(format!(
+                "expected {} bytes of densities, got {}",
+                4 * count,
+                body.len()
+            )));
+        }
+        let values = body
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        DensityGrid::new(dims[0], dims[1], dims[2], values)
+    }
+
+    pub fn load_raw<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let data = fs::read(path).map_err(Error::at(path))?;
+        DensityGrid::read_raw(&mut data.as_slice()).map_err(|e| match e {
+            Error::Parse(msg) => Error::Parse(format!("{}: {}", path.display(), msg)),
+            e => e,
+        })
+    }
+
+    // Write in the format of `read_raw`
+    pub fn to_raw(&self) -> Vec<u8> {
+        let mut out = format!("density_grid {} {} {}\n", self.nx, self.ny, self.nz).into_bytes();
+        for d in &self.data {
+            out.extend_from_slice(&d.to_le_bytes());
+        }
+        out
+    }
+
+    pub fn max_density(&self) -> f32 {
+        self.max
+    }
+
+    fn at(&self, i: usize, j: usize, k: usize) -> f32 {
+        self.data[(k * self.ny + j) * self.nx + i]
+    }
+
+    // Density at `p` in [0, 1]^3, clamped to the volume
+    pub fn density(&self, p: &Vec3) -> f32 {
+        let locate = |c: f32, n: usize| {
+            let x = c.clamp(0.0, 1.0) * (n - 1) as f32;
+            let i = (x as usize).min(n - 2);
+            (i, x - i as f32)
+        };
+        let (i, u) = locate(p.x, self.nx);
+        let (j, v) = locate(p.y, self.ny);
+        let (k, w) = locate(p.z, self.nz);
+
+        let mut sum = 0.0;
+        for (dk, wk) in [(0, 1.0 - w), (1, w)] {
+            for (dj, wj) in [(0, 1.0 - v), (1, v)] {
+                for (di, wi) in [(0, 1.0 - u), (1, u)] {
+                    sum += wi * wj * wk * self.at(i + di, j + dj, k + dk);
+                }
+            }
+        }
+        sum
+    }
+}
+
+// Volume of varying density given by a grid stretched over an axis-aligned
+// box, e.g. a cloud or an explosion. Collisions are sampled with delta
+// tracking: tentative collisions at the rate of the maximum density are
+// accepted with the probability density / maximum, which samples the real
+// collisions exactly without stepping through the cells.
+pub struct GridMedium {
+    pub bounds: Aabb,
+    pub grid: Arc<DensityGrid>,
+    // density of the grid value 1, per unit length
+    pub density_scale: f32,
+    pub phase: Arc<dyn Material>,
+}
+
+impl GridMedium {
+    pub fn new(
+        bounds: Aabb,
+        grid: Arc<DensityGrid>,
+        density_scale: f32,
+        phase: Arc<dyn Material>,
+    ) -> Self {
+        GridMedium {
+            bounds,
+            grid,
+            density_scale,
+            phase,
+        }
+    }
+
+    // Medium with a Henyey-Greenstein phase function of asymmetry `g`
+    pub fn henyey_greenstein(
+        bounds: Aabb,
+        grid: Arc<DensityGrid>,
+        density_scale: f32,
+        albedo: Vec3,
+        g: f32,
+    ) -> Self {
+        let phase = Arc::new(HenyeyGreenstein::new(albedo, g));
+        GridMedium::new(bounds, grid, density_scale, phase)
+    }
+
+    // Density per unit length at `p`
+    pub fn density(&self, p: &Vec3) -> f32 {
+        let size = self.bounds.max - self.bounds.min;
+        let local = *p - self.bounds.min;
+        let local = Vec3::new(local.x / size.x, local.y / size.y, local.z / size.z);
+        self.density_scale * self.grid.density(&local)
+    }
+
+    // Parameters of the part of the ray inside the bounds, between `t_min`
+    // and `t_max`
+    fn segment(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32)> {
+        let mut t0 = t_min;
+        let mut t1 = t_max;
+        for axis in 0..3 {
+            let inv_d = 1.0 / r.direction[axis];
+            let mut near = (self.bounds.min[axis] - r.origin[axis]) * inv_d;
+            let mut far = (self.bounds.max[axis] - r.origin[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut near, &mut far);
+            }
+            t0 = t0.max(near);
+            t1 = t1.min(far);
+        }
+        if t0 < t1 {
+            Some((t0, t1))
+        } else {
+            None
+        }
+    }
+
+    // Probability that light crosses the medium from `r.origin` between
+    // `t_min` and `t_max`, estimated without bias by ratio tracking. For
+    // estimators that need a transmittance rather than a collision, such as
+    // shadow rays; the current integrator only uses `hit`.
+    pub fn transmittance(&self, r: &Ray, t_min: f32, t_max: f32) -> f32 {
+        let majorant = self.density_scale * self.grid.max_density();
+        let (t0, t1) = match self.segment(r, t_min, t_max) {
+            Some(segment) if majorant > 0.0 => segment,
+            _ => return 1.0,
+        };
+        let length = r.direction.length();
+        let mut t = t0;
+        let mut transmittance = 1.0;
+        loop {
+            t -= (1.0 - random::next_f32()).ln() / (majorant * length);
+            if t >= t1 {
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.density(&r.point_at_parameter(t)) / majorant;
+        }
+    }
+}
+
+impl Hitable for GridMedium {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let majorant = self.density_scale * self.grid.max_density();
+        if majorant <= 0.0 {
+            return None;
+        }
+        let (t0, t1) = self.segment(r, t_min, t_max)?;
+        let length = r.direction.length();
+
+        // delta tracking
+        let mut t = t0;
+        loop {
+            t -= (1.0 - random::next_f32()).ln() / (majorant * length);
+            if t >= t1 {
+                return None;
+            }
+            let p = r.point_at_parameter(t);
+            if random::next_f32() * majorant < self.density(&p) {
+                return Some(HitRecord {
+                    t,
+                    p,
+                    // there is no surface: any normal will do
+                    normal: Vec3::new(1.0, 0.0, 0.0),
+                    front_face: true,
+                    u: 0.0,
+                    v: 0.0,
+                    dpdu: Vec3::new(0.0, 0.0, 0.0),
+                    dpdv: Vec3::new(0.0, 0.0, 0.0),
+                    material: self.phase.borrow(),
+                });
+            }
+        }
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box() -> Aabb {
+        Aabb::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn trilinear_density() {
+        let grid = DensityGrid::from_fn(3, 2, 2, |p| p.x + 2.0 * p.y * p.z).unwrap();
+        assert_eq!(grid.max_density(), 3.0);
+        assert_eq!(grid.density(&Vec3::new(0.5, 0.0, 0.0)), 0.5);
+        assert!((grid.density(&Vec3::new(0.25, 0.5, 0.5)) - 0.75).abs() < 1e-6);
+        assert_eq!(grid.density(&Vec3::new(2.0, 1.0, 1.0)), 3.0);
+
+        assert!(DensityGrid::new(2, 2, 2, vec![0.0; 7]).is_err());
+        assert!(DensityGrid::new(2, 2, 2, vec![-1.0; 8]).is_err());
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        let grid = DensityGrid::cloud(8, 3).unwrap();
+        assert!(grid.max_density() > 0.5 && grid.max_density() <= 1.0);
+        let raw = grid.to_raw();
+        let back = DensityGrid::read_raw(&mut raw.as_slice()).unwrap();
+        assert_eq!((back.nx, back.ny, back.nz), (8, 8, 8));
+        assert_eq!(back.data, grid.data);
+
+        assert!(DensityGrid::read_raw(&mut &raw[..raw.len() - 1]).is_err());
+        assert!(DensityGrid::read_raw(&mut "density_grid 2 2\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn tracking_matches_the_optical_depth() {
+        // density rising linearly along x from 0 to 2: optical depth 1 across
+        let grid = Arc::new(DensityGrid::from_fn(5, 2, 2, |p| 2.0 * p.x).unwrap());
+        let medium =
+            GridMedium::henyey_greenstein(unit_box(), grid, 1.0, Vec3::new(1.0, 1.0, 1.0), 0.5);
+        let r = Ray::new(Vec3::new(-1.0, 0.5, 0.5), Vec3::new(2.0, 0.0, 0.0));
+        let expected = (-1.0f32).exp();
+
+        random::seed(8);
+        const N: usize = 20_000;
+        let passed = (0..N)
+            .filter(|_| medium.hit(&r, 0.001, f32::MAX).is_none())
+            .count();
+        assert!((passed as f32 / N as f32 - expected).abs() < 0.015);
+        let ratio = (0..N)
+            .map(|_| medium.transmittance(&r, 0.001, f32::MAX))
+            .sum::<f32>()
+            / N as f32;
+        assert!((ratio - expected).abs() < 0.01);
+
+        // collisions happen more where the medium is denser: the second half
+        // should see about 1.86 times as many
+        let mut first_half = 0;
+        let mut second_half = 0;
+        for _ in 0..N {
+            if let Some(rec) = medium.hit(&r, 0.001, f32::MAX) {
+                assert!(rec.p.x >= 0.0 && rec.p.x <= 1.0);
+                if rec.p.x < 0.5 {
+                    first_half += 1;
+                } else {
+                    second_half += 1;
+                }
+            }
+        }
+        assert!(2 * second_half > 3 * first_half);
+    }
+}
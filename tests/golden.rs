@@ -0,0 +1,106 @@
+// Golden-image regression tests: small fixed-seed renders of the built-in
+// scenes are compared with reference images stored in `tests/golden/`.
+//
+// On failure the render, the reference and a false-color difference image are
+// written to `target/golden-diff/`. After an intended change of the output,
+// regenerate the references with
+//
+//     UPDATE_GOLDEN=1 cargo test --test golden
+use rust_rtow::camera::Camera;
+use rust_rtow::hitable::Hitable;
+use rust_rtow::image::Image;
+use rust_rtow::metrics;
+use rust_rtow::renderer::{Background, RenderSettings, Renderer};
+use rust_rtow::scene;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const SETTINGS: RenderSettings = RenderSettings {
+    width: 64,
+    height: 48,
+    samples: 16,
+    max_depth: 50,
+    crop: None,
+    seed: Some(1),
+    background: Background::Sky,
+    spectral: false,
+};
+
+// Renders at the same seed match their reference up to floating point noise
+// and the 8-bit quantization of the PPM files (RMSE about 0.002). Changing
+// the noise pattern alone, e.g. by drawing random numbers in another order,
+// gives an RMSE around 0.035, and a 5% darker albedo around 0.01, so both
+// fail and the references have to be regenerated after checking the images.
+//
+// Maximum RMSE, in linear color
+const MAX_RMSE: f32 = 0.005;
+// Maximum mean perceptual error, on images blurred by `BLUR_SIGMA` pixels
+const MAX_PERCEPTUAL_ERROR: f32 = 0.004;
+const BLUR_SIGMA: f32 = 1.0;
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn diff_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("target/golden-diff")
+}
+
+fn render(world: &dyn Hitable, cam: &Camera) -> Image {
+    Renderer::new(SETTINGS)
+        .render(world, cam)
+        .expect("golden render settings are valid")
+        .image
+}
+
+fn check_golden(name: &str, image: &Image) {
+    let reference_path = golden_dir().join(format!("{}.ppm", name));
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(golden_dir()).unwrap();
+        image.save_ppm(&reference_path).unwrap();
+        return;
+    }
+
+    let reference = Image::load_ppm(&reference_path).unwrap_or_else(|e| {
+        panic!(
+            "cannot load the reference image ({}); run with UPDATE_GOLDEN=1 to create it",
+            e
+        )
+    });
+    let rmse = metrics::rmse(image, &reference).unwrap();
+    let errors = metrics::perceptual_error_map(image, &reference, BLUR_SIGMA).unwrap();
+    let perceptual = errors.iter().sum::<f32>() / errors.len() as f32;
+
+    if rmse > MAX_RMSE || perceptual > MAX_PERCEPTUAL_ERROR {
+        let dir = diff_dir();
+        fs::create_dir_all(&dir).unwrap();
+        image
+            .save_ppm(dir.join(format!("{}-actual.ppm", name)))
+            .unwrap();
+        reference
+            .save_ppm(dir.join(format!("{}-expected.ppm", name)))
+            .unwrap();
+        metrics::false_color(image.width, image.height, &errors, 0.1)
+            .save_ppm(dir.join(format!("{}-diff.ppm", name)))
+            .unwrap();
+        panic!(
+            "{} differs from its reference: RMSE {:.4} (max {}), perceptual error {:.4} (max {}); images written to {}",
+            name,
+            rmse,
+            MAX_RMSE,
+            perceptual,
+            MAX_PERCEPTUAL_ERROR,
+            dir.display()
+        );
+    }
+}
+
+#[test]
+fn golden_random_scene() {
+    let world = scene::random_scene_with_seed(1);
+    let cam = scene::random_scene_camera(SETTINGS.aspect());
+    check_golden("random_scene", &render(&world, &cam));
+}
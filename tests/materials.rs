@@ -0,0 +1,658 @@
+// Statistical tests of the physical correctness of the materials: furnace
+// tests, energy conservation, reciprocity of the BRDFs, and chi-square tests
+// of the sampled directions against the declared densities.
+use rust_rtow::camera::Camera;
+use rust_rtow::constant_medium::ConstantMedium;
+use rust_rtow::hitable::HitRecord;
+use rust_rtow::hitable_list::HitableList;
+use rust_rtow::material::{
+    Coated, Conductor, Dielectric, HenyeyGreenstein, Ior, Isotropic, Lambertian, Material, Metal,
+    Mix, RoughDielectric, Subsurface,
+};
+use rust_rtow::principled::Principled;
+use rust_rtow::random;
+use rust_rtow::ray::Ray;
+use rust_rtow::renderer::{Background, RenderSettings, Renderer};
+use rust_rtow::sphere::Sphere;
+use rust_rtow::thin_film::ThinFilm;
+use rust_rtow::vec3::Vec3;
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// Unit direction of polar angle `theta` around +z
+fn direction(theta: f32, phi: f32) -> Vec3 {
+    Vec3::new(
+        theta.sin() * phi.cos(),
+        theta.sin() * phi.sin(),
+        theta.cos(),
+    )
+}
+
+fn random_direction() -> Vec3 {
+    let z = 2.0 * random::next_f32() - 1.0;
+    let phi = 2.0 * std::f32::consts::PI * random::next_f32();
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+// Incoming ray arriving at the origin from direction `wo`
+fn incoming(wo: Vec3) -> Ray {
+    Ray::new(wo, -wo)
+}
+
+// Materials with a white albedo, which must not lose energy when lit uniformly
+fn lossless_materials() -> Vec<(&'static str, Arc<dyn Material>)> {
+    vec![
+        ("lambertian", Arc::new(Lambertian::new(1.0, 1.0, 1.0))),
+        ("mirror", Arc::new(Metal::new((1.0, 1.0, 1.0), 0.0))),
+        ("glass", Arc::new(Dielectric::new(1.5))),
+        (
+            "isotropic",
+            Arc::new(Isotropic::new(Vec3::new(1.0, 1.0, 1.0))),
+        ),
+        (
+            "mix",
+            Arc::new(Mix::new(
+                Arc::new(Lambertian::new(1.0, 1.0, 1.0)),
+                Arc::new(Metal::new((1.0, 1.0, 1.0), 0.0)),
+                0.5,
+            )),
+        ),
+        (
+            "subsurface",
+            Arc::new(Subsurface::new(
+                Vec3::new(1.0, 1.0, 1.0),
+                Vec3::new(0.5, 0.5, 0.5),
+                1.5,
+            )),
+        ),
+    ]
+}
+
+// Materials that may absorb some light
+fn all_materials() -> Vec<(&'static str, Arc<dyn Material>)> {
+    let mut materials = lossless_materials();
+    materials.push((
+        "lambertian colored",
+        Arc::new(Lambertian::new(0.8, 0.3, 0.1)),
+    ));
+    materials.push(("fuzzy metal", Arc::new(Metal::new((1.0, 1.0, 1.0), 0.5))));
+    materials.push(("rough metal", Arc::new(Metal::new((0.9, 0.8, 0.7), 1.0))));
+    materials.push(("diamond", Arc::new(Dielectric::new(2.4))));
+    materials.push(("polished gold", Arc::new(Conductor::gold(0.05))));
+    materials.push((
+        "varnish",
+        Arc::new(Coated::new(Arc::new(Lambertian::new(0.8, 0.3, 0.1)), 1.5)),
+    ));
+    materials.push((
+        "car paint",
+        Arc::new(Coated::new(Arc::new(Metal::new((0.7, 0.1, 0.1), 0.4)), 1.5)),
+    ));
+    materials.push(("frosted glass", Arc::new(RoughDielectric::new(1.5, 0.05))));
+    materials.extend(rough_dielectrics());
+    materials.extend(conductors());
+    // Burley's diffuse lobe gains energy at grazing angles when rough, so the
+    // principled materials are only checked without it
+    materials.push((
+        "principled metal",
+        Arc::new(
+            Principled::new(Vec3::new(0.9, 0.6, 0.3))
+                .with_metallic(1.0)
+                .with_roughness(0.3),
+        ),
+    ));
+    materials.push(("principled glass", principled_glass()));
+    materials
+}
+
+fn conductors() -> Vec<(&'static str, Arc<dyn Material>)> {
+    vec![
+        ("gold", Arc::new(Conductor::gold(0.5))),
+        ("copper", Arc::new(Conductor::copper(0.7))),
+        ("aluminum", Arc::new(Conductor::aluminum(1.0))),
+    ]
+}
+
+fn principled() -> Vec<(&'static str, Arc<dyn Material>)> {
+    vec![
+        (
+            "principled plastic",
+            Arc::new(Principled::new(Vec3::new(0.8, 0.3, 0.1)).with_roughness(0.4)),
+        ),
+        (
+            "principled metal",
+            Arc::new(
+                Principled::new(Vec3::new(0.9, 0.6, 0.3))
+                    .with_metallic(1.0)
+                    .with_roughness(0.3),
+            ),
+        ),
+        (
+            "principled cloth",
+            Arc::new(
+                Principled::new(Vec3::new(0.2, 0.3, 0.8))
+                    .with_roughness(1.0)
+                    .with_sheen(1.0)
+                    .with_sheen_tint(0.5)
+                    .with_clearcoat(1.0)
+                    .with_clearcoat_roughness(0.3),
+            ),
+        ),
+    ]
+}
+
+fn principled_glass() -> Arc<dyn Material> {
+    Arc::new(
+        Principled::new(Vec3::new(1.0, 1.0, 1.0))
+            .with_roughness(0.8)
+            .with_transmission(1.0, 1.5),
+    )
+}
+
+fn rough_dielectrics() -> Vec<(&'static str, Arc<dyn Material>)> {
+    vec![
+        ("rough glass", Arc::new(RoughDielectric::new(1.5, 0.8))),
+        (
+            "rough glass inside",
+            Arc::new(RoughDielectric::new(1.0 / 1.5, 0.8)),
+        ),
+    ]
+}
+
+// Phase functions and materials implementing `eval` and `pdf`
+fn sampled_materials() -> Vec<(&'static str, Arc<dyn Material>)> {
+    let mut materials = evaluable_materials();
+    materials.push((
+        "isotropic colored",
+        Arc::new(Isotropic::new(Vec3::new(0.2, 0.5, 0.9))),
+    ));
+    materials.push((
+        "henyey-greenstein forward",
+        Arc::new(HenyeyGreenstein::new(Vec3::new(1.0, 1.0, 1.0), 0.7)),
+    ));
+    materials.push((
+        "henyey-greenstein backward",
+        Arc::new(HenyeyGreenstein::new(Vec3::new(0.8, 0.8, 0.8), -0.3)),
+    ));
+    // refraction is not reciprocal with the radiance unscaled by the indices
+    materials.extend(rough_dielectrics());
+    materials.push(("principled glass", principled_glass()));
+    materials
+}
+
+// Surface materials implementing `eval` and `pdf`
+fn evaluable_materials() -> Vec<(&'static str, Arc<dyn Material>)> {
+    let mut materials: Vec<(&'static str, Arc<dyn Material>)> = vec![
+        ("lambertian", Arc::new(Lambertian::new(1.0, 1.0, 1.0))),
+        (
+            "lambertian colored",
+            Arc::new(Lambertian::new(0.8, 0.3, 0.1)),
+        ),
+    ];
+    materials.extend(conductors());
+    materials.extend(principled());
+    materials.push((
+        "mix colored",
+        Arc::new(Mix::new(
+            Arc::new(Lambertian::new(0.8, 0.3, 0.1)),
+            Arc::new(Conductor::gold(0.4)),
+            0.3,
+        )),
+    ));
+    materials
+}
+
+// In a uniform white environment, lossless objects must look exactly like
+// the environment: whatever the path, the light reaching the camera has the
+// radiance of the environment.
+fn check_furnace(name: &str, world: &HitableList) {
+    // no path may be cut short
+    check_furnace_at_depth(name, world, 1000);
+}
+
+fn check_furnace_at_depth(name: &str, world: &HitableList, max_depth: u32) {
+    let environment = Vec3::new(0.5, 0.5, 0.5);
+    let settings = RenderSettings {
+        width: 12,
+        height: 8,
+        samples: 8,
+        max_depth,
+        seed: Some(7),
+        background: Background::Uniform(environment),
+        ..Default::default()
+    };
+    let lookfrom = Vec3::new(0.0, 0.0, 3.0);
+    let lookat = Vec3::new(0.0, 0.0, 0.0);
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let cam = Camera::new(lookfrom, lookat, vup, 40.0, settings.aspect(), 0.0, 3.0);
+
+    let image = Renderer::new(settings).render(world, &cam).unwrap().image;
+    for pix in &image.pixels {
+        assert!(
+            (*pix - environment).length() < 1e-4,
+            "{}: furnace returned {:?} instead of {:?}",
+            name,
+            pix,
+            environment
+        );
+    }
+}
+
+fn unit_sphere(material: Arc<dyn Material>) -> Sphere {
+    Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, material)
+}
+
+#[test]
+fn nested_dielectrics_furnace() {
+    // water overlapping the glass it is in, a bubble of air in the water and
+    // an ice cube crossing the glass
+    let glass = Arc::new(Dielectric::new(1.5).with_priority(2));
+    let water = Arc::new(Dielectric::new(1.33).with_priority(1));
+    let air = Arc::new(Dielectric::new(1.0).with_priority(3));
+    let ice = Arc::new(RoughDielectric::new(1.31, 0.0).with_priority(4));
+    let world = HitableList {
+        hitables: vec![
+            Arc::new(unit_sphere(glass)),
+            Arc::new(Sphere::new(Vec3::new(0.0, 0.3, 0.0), 0.9, water)),
+            Arc::new(Sphere::new(Vec3::new(0.2, 0.0, 0.3), 0.3, air)),
+            Arc::new(Sphere::new(Vec3::new(-0.5, -0.6, 0.4), 0.4, ice)),
+        ],
+    };
+    check_furnace("glass of water", &world);
+}
+
+#[test]
+fn white_furnace() {
+    for (name, material) in lossless_materials() {
+        let world = HitableList {
+            hitables: vec![Arc::new(unit_sphere(material))],
+        };
+        check_furnace(name, &world);
+    }
+
+    let smoke = ConstantMedium::isotropic(
+        unit_sphere(Arc::new(Lambertian::new(0.5, 0.5, 0.5))),
+        2.0,
+        Vec3::new(1.0, 1.0, 1.0),
+    );
+    let world = HitableList {
+        hitables: vec![Arc::new(smoke)],
+    };
+    check_furnace("constant medium", &world);
+}
+
+// The random walks in dense media take far more steps than `max_depth`, which
+// only limits the bounces on surfaces.
+#[test]
+fn subsurface_furnace() {
+    let wax = Subsurface::new(Vec3::new(1.0, 1.0, 1.0), Vec3::new(0.02, 0.02, 0.02), 1.5);
+    let world = HitableList {
+        hitables: vec![Arc::new(unit_sphere(Arc::new(wax)))],
+    };
+    check_furnace_at_depth(
+        "dense subsurface",
+        &world,
+        RenderSettings::default().max_depth,
+    );
+}
+
+// In spectral mode, energy is conserved on average over the wavelengths,
+// including through dispersive glass and thin films which keep only the hero
+// wavelength.
+#[test]
+fn spectral_furnace() {
+    let environment = Vec3::new(0.5, 0.5, 0.5);
+    let settings = RenderSettings {
+        width: 12,
+        height: 8,
+        samples: 256,
+        max_depth: 1000,
+        seed: Some(7),
+        background: Background::Uniform(environment),
+        spectral: true,
+        ..Default::default()
+    };
+    let cam = Camera::new(
+        Vec3::new(0.0, 0.0, 3.0),
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        40.0,
+        settings.aspect(),
+        0.0,
+        3.0,
+    );
+    let materials: Vec<(&str, Arc<dyn Material>)> = vec![
+        ("lambertian", Arc::new(Lambertian::new(1.0, 1.0, 1.0))),
+        ("BK7", Arc::new(Dielectric::with_ior(Ior::BK7))),
+        ("SF11", Arc::new(Dielectric::with_ior(Ior::SF11))),
+        (
+            "soap bubble",
+            Arc::new(Dielectric::new(1.0).with_film(ThinFilm::new(400.0, 1.33))),
+        ),
+    ];
+    for (name, material) in materials {
+        let world = HitableList {
+            hitables: vec![Arc::new(unit_sphere(material))],
+        };
+        let image = Renderer::new(settings).render(&world, &cam).unwrap().image;
+        let mean = image
+            .pixels
+            .iter()
+            .fold(Vec3::new(0.0, 0.0, 0.0), |acc, p| acc + *p)
+            / image.pixels.len() as f32;
+        assert!(
+            (mean - environment).length() < 0.02,
+            "{}: spectral furnace returned {:?}",
+            name,
+            mean
+        );
+    }
+}
+
+// Dispersive glass bends short wavelengths more
+#[test]
+fn dispersion() {
+    assert!((Ior::BK7.at(None) - 1.5168).abs() < 1e-3);
+    assert!((Ior::SF11.at(None) - 1.7847).abs() < 1e-3);
+    let cauchy = Ior::Cauchy { a: 1.5, b: 0.004 };
+    for ior in [Ior::BK7, Ior::SF11, cauchy] {
+        assert!(ior.at(Some(450.0)) > ior.at(Some(650.0)) + 0.004);
+    }
+    assert_eq!(Ior::Constant(1.5).at(Some(450.0)), 1.5);
+
+    random::seed(4);
+    let glass = Dielectric::with_ior(Ior::SF11);
+    let rec = HitRecord::facing_z(&glass);
+    let refracted = |wavelength: f32| loop {
+        let r = Ray {
+            wavelength: Some(wavelength),
+            ..incoming(direction(0.8, 0.0))
+        };
+        let d = glass.scatter(&r, &rec).unwrap().scattered.direction;
+        if d.z < 0.0 {
+            return d.unit_vector();
+        }
+    };
+    let (blue, red) = (refracted(450.0), refracted(650.0));
+    // closer to the normal
+    assert!(-blue.z > -red.z + 1e-3);
+}
+
+// The light leaving in all directions never exceeds the light received, for
+// any incident direction: the mean scattering weight is at most one.
+#[test]
+fn energy_conservation() {
+    const SAMPLES: usize = 20_000;
+    random::seed(1);
+
+    for (name, material) in all_materials() {
+        for &theta in &[0.0f32, 0.5, 1.0, 1.4, 1.55] {
+            let wo = direction(theta, 0.3);
+            let rec = HitRecord::facing_z(material.as_ref());
+            let mut total = Vec3::new(0.0, 0.0, 0.0);
+            for _ in 0..SAMPLES {
+                if let Some(srec) = material.scatter(&incoming(wo), &rec) {
+                    // up to rounding
+                    let a = srec.attenuation;
+                    let max = 1.0 + 1e-6;
+                    assert!(
+                        a.x <= max && a.y <= max && a.z <= max,
+                        "{}: weight {:?} above one",
+                        name,
+                        a
+                    );
+                    total += a;
+                }
+            }
+            let albedo = total / SAMPLES as f32;
+            assert!(
+                albedo.x <= 1.0 + 1e-3 && albedo.y <= 1.0 + 1e-3 && albedo.z <= 1.0 + 1e-3,
+                "{}: directional albedo {:?} at theta = {}",
+                name,
+                albedo,
+                theta
+            );
+        }
+    }
+}
+
+// Helmholtz reciprocity: f(wo, wi) = f(wi, wo). `eval` includes the cosine
+// of `wi`, which is divided out.
+#[test]
+fn reciprocity() {
+    random::seed(2);
+
+    for (name, material) in evaluable_materials() {
+        let rec = HitRecord::facing_z(material.as_ref());
+        for _ in 0..1000 {
+            let wo = random_direction();
+            let wi = random_direction();
+            let (cos_o, cos_i) = (wo.z.abs(), wi.z.abs());
+            if cos_o < 1e-3 || cos_i < 1e-3 {
+                continue;
+            }
+            let f = material.eval(&rec, &wo, &wi) / cos_i;
+            let g = material.eval(&rec, &wi, &wo) / cos_o;
+            assert!(
+                (f - g).length() <= 1e-4 * (1.0 + f.length()),
+                "{}: f(wo, wi) = {:?} but f(wi, wo) = {:?}",
+                name,
+                f,
+                g
+            );
+        }
+    }
+}
+
+// The weight returned by `scatter` is `eval / pdf` at the sampled direction.
+#[test]
+fn scatter_weight_matches_eval_and_pdf() {
+    random::seed(3);
+
+    for (name, material) in sampled_materials() {
+        let rec = HitRecord::facing_z(material.as_ref());
+        for &theta in &[0.2f32, 0.9, 1.5] {
+            let wo = direction(theta, 1.0);
+            for _ in 0..1000 {
+                let srec = match material.scatter(&incoming(wo), &rec) {
+                    Some(srec) => srec,
+                    None => continue,
+                };
+                let wi = srec.scattered.direction.unit_vector();
+                let pdf = material.pdf(&rec, &wo, &wi);
+                if pdf < 1e-3 {
+                    continue;
+                }
+                let expected = material.eval(&rec, &wo, &wi) / pdf;
+                assert!(
+                    (srec.attenuation - expected).length() <= 1e-3 * (1.0 + expected.length()),
+                    "{}: weight {:?} but eval / pdf = {:?}",
+                    name,
+                    srec.attenuation,
+                    expected
+                );
+            }
+        }
+    }
+}
+
+// Chi-square goodness of fit test
+// -------------------------------
+
+const COS_BINS: usize = 10;
+const PHI_BINS: usize = 20;
+
+fn bin_of(w: &Vec3) -> usize {
+    let cos_theta = f64::from(w.z).clamp(-1.0, 1.0);
+    let phi = f64::from(w.y).atan2(f64::from(w.x)).rem_euclid(2.0 * PI);
+    let i = (((cos_theta + 1.0) / 2.0 * COS_BINS as f64) as usize).min(COS_BINS - 1);
+    let j = ((phi / (2.0 * PI) * PHI_BINS as f64) as usize).min(PHI_BINS - 1);
+    i * PHI_BINS + j
+}
+
+// Integral of the pdf over each bin of the sphere, by the midpoint rule
+fn expected_frequencies(pdf: &dyn Fn(&Vec3) -> f32, samples: usize) -> Vec<f64> {
+    // fine enough for the refraction lobes, peaked near the poles
+    const SUB: usize = 32;
+    let d_cos = 2.0 / COS_BINS as f64;
+    let d_phi = 2.0 * PI / PHI_BINS as f64;
+    let mut freq = vec![0.0; COS_BINS * PHI_BINS];
+    for i in 0..COS_BINS {
+        for j in 0..PHI_BINS {
+            let mut integral = 0.0;
+            for a in 0..SUB {
+                for b in 0..SUB {
+                    let cos_theta = -1.0 + (i as f64 + (a as f64 + 0.5) / SUB as f64) * d_cos;
+                    let phi = (j as f64 + (b as f64 + 0.5) / SUB as f64) * d_phi;
+                    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                    let w = Vec3::new(
+                        (sin_theta * phi.cos()) as f32,
+                        (sin_theta * phi.sin()) as f32,
+                        cos_theta as f32,
+                    );
+                    integral += f64::from(pdf(&w));
+                }
+            }
+            freq[i * PHI_BINS + j] = integral * d_cos * d_phi / (SUB * SUB) as f64 * samples as f64;
+        }
+    }
+    freq
+}
+
+// Regularized lower incomplete gamma function P(a, x)
+fn gamma_p(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let ln_gamma_a = ln_gamma(a);
+    if x < a + 1.0 {
+        // series expansion
+        let mut sum = 1.0 / a;
+        let mut term = sum;
+        let mut n = a;
+        for _ in 0..1000 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+            if term.abs() < sum.abs() * 1e-15 {
+                break;
+            }
+        }
+        sum * (-x + a * x.ln() - ln_gamma_a).exp()
+    } else {
+        // continued fraction for Q(a, x) (modified Lentz)
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..1000 {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < 1e-15 {
+                break;
+            }
+        }
+        1.0 - (-x + a * x.ln() - ln_gamma_a).exp() * h
+    }
+}
+
+// Lanczos approximation
+fn ln_gamma(x: f64) -> f64 {
+    const COEF: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.001_208_650_973_866_179,
+        -0.000_005_395_239_384_953,
+    ];
+    let mut y = x;
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let mut ser = 1.000_000_000_190_015;
+    for c in COEF.iter() {
+        y += 1.0;
+        ser += c / y;
+    }
+    -tmp + (2.506_628_274_631_000_5 * ser / x).ln()
+}
+
+// p-value of the chi-square test of `observed` against `expected`. Bins
+// expected to get fewer than 5 samples are pooled together.
+fn chi_square_p_value(observed: &[f64], expected: &[f64]) -> f64 {
+    let mut chi2 = 0.0;
+    let mut dof = 0;
+    let (mut pooled_obs, mut pooled_exp) = (0.0, 0.0);
+    for (o, e) in observed.iter().zip(expected) {
+        if *e < 5.0 {
+            pooled_obs += o;
+            pooled_exp += e;
+        } else {
+            chi2 += (o - e) * (o - e) / e;
+            dof += 1;
+        }
+    }
+    if pooled_exp >= 5.0 {
+        chi2 += (pooled_obs - pooled_exp) * (pooled_obs - pooled_exp) / pooled_exp;
+        dof += 1;
+    } else if pooled_obs > 0.0 && pooled_exp == 0.0 {
+        // samples where the pdf is zero
+        return 0.0;
+    }
+    1.0 - gamma_p((dof - 1) as f64 / 2.0, chi2 / 2.0)
+}
+
+#[test]
+fn gamma_p_matches_known_values() {
+    // chi-square CDF with 2 degrees of freedom is 1 - exp(-x / 2)
+    assert!((gamma_p(1.0, 1.5) - (1.0 - (-1.5f64).exp())).abs() < 1e-9);
+    assert!((gamma_p(1.0, 7.0) - (1.0 - (-7.0f64).exp())).abs() < 1e-9);
+    // P(0.5, x) = erf(sqrt(x)), erf(1) = 0.8427007929
+    assert!((gamma_p(0.5, 1.0) - 0.842_700_792_9).abs() < 1e-8);
+}
+
+// The directions sampled by `scatter` follow the density declared by `pdf`.
+#[test]
+fn sampled_directions_match_pdf() {
+    const SAMPLES: usize = 200_000;
+    let tests = sampled_materials();
+    let incidences = [0.1f32, 0.8, 1.45];
+    // Significance level of the whole test, split between the individual tests
+    let alpha = 0.01 / (tests.len() * incidences.len()) as f64;
+
+    random::seed(4);
+    for (name, material) in tests {
+        let rec = HitRecord::facing_z(material.as_ref());
+        for &theta in &incidences {
+            let wo = direction(theta, 2.0);
+            let mut observed = vec![0.0; COS_BINS * PHI_BINS];
+            for _ in 0..SAMPLES {
+                if let Some(srec) = material.scatter(&incoming(wo), &rec) {
+                    observed[bin_of(&srec.scattered.direction.unit_vector())] += 1.0;
+                }
+            }
+            let expected = expected_frequencies(&|wi| material.pdf(&rec, &wo, wi), SAMPLES);
+            let p = chi_square_p_value(&observed, &expected);
+            assert!(
+                p > alpha,
+                "{}: sampled directions do not follow the pdf at theta = {} (p-value {:e})",
+                name,
+                theta,
+                p
+            );
+        }
+    }
+}